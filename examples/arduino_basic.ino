@@ -36,7 +36,7 @@ void loop() {
   // --- Potentiometer: send value over WebSocket and OSC ---
   int potValue = analogRead(POT_PIN);
 
-  // Broadcast to WebSocket clients as JSON: {"id":"pot","value":512}
+  // Broadcast to WebSocket clients as JSON: {"id":"pot","value":512,"ts":...,"source":"serial"}
   Serial.print("ws:pot,");
   Serial.println(potValue);
 
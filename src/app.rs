@@ -1,17 +1,19 @@
-//! App struct: unified event loop, state management, key watchdog.
+//! App struct: TUI event loop and state management on top of the engine.
 //!
 //! The main thread runs: TUI rendering, event processing, and enigo
 //! (macOS requires CGEvent calls on the main thread).
 
-use crate::bridge::{self, websocket};
+use crate::bridge::midi::MidiBridge;
+use crate::bridge::osc::OscBridge;
+use crate::bridge::websocket::WsBridge;
+use crate::bridge::{midi_recorder, osc, websocket, DispatchOutcome, DispatchStatus};
 use crate::config::Config;
-use crate::protocol;
+use crate::engine::{Engine, EngineEvent};
 use crate::serial;
 use crate::tui::{self, event::TuiAction, layout, widgets::Popup};
 use anyhow::Result;
-use std::sync::atomic::Ordering;
-use std::sync::mpsc;
-use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::time::Duration;
 
 /// A single log entry displayed in the TUI.
 #[derive(Debug, Clone)]
@@ -19,6 +21,8 @@ pub struct LogEntry {
     pub timestamp: String,
     pub raw_line: String,
     pub result: String,
+    /// The worst status of the line's commands, for colouring.
+    pub status: DispatchStatus,
 }
 
 impl LogEntry {
@@ -27,6 +31,15 @@ impl LogEntry {
             timestamp: now_hms(),
             raw_line,
             result,
+            status: DispatchStatus::Ok,
+        }
+    }
+
+    /// An entry for a dispatched line.
+    pub fn dispatched(raw_line: String, outcomes: &[DispatchOutcome]) -> Self {
+        Self {
+            status: DispatchOutcome::worst(outcomes),
+            ..Self::new(raw_line, DispatchOutcome::join(outcomes))
         }
     }
 }
@@ -77,16 +90,23 @@ pub struct AppState {
     pub serial_port_name: Option<String>,
     pub baud_rate: u32,
     pub midi_port_name: Option<String>,
+    pub midi_recording: bool,
     pub ws_enabled: bool,
     pub ws_port: u16,
     pub ws_client_count: usize,
+    pub ws_dropped: u64,
     pub osc_enabled: bool,
     pub osc_remote: String,
+    pub osc_links: Vec<osc::DestinationStatus>,
     pub log_lines: Vec<LogEntry>,
     pub max_log_lines: usize,
     pub show_timestamps: bool,
     pub scroll_offset: usize,
     pub active_popup: Option<Popup>,
+    /// The active profile, when the config has any.
+    pub profile: Option<String>,
+    /// Commands that failed since startup.
+    pub dispatch_errors: u64,
 }
 
 impl AppState {
@@ -96,16 +116,27 @@ impl AppState {
             serial_port_name: None,
             baud_rate: config.serial.baud_rate,
             midi_port_name: None,
+            midi_recording: false,
             ws_enabled: config.websocket.enabled,
             ws_port: config.websocket.port,
             ws_client_count: 0,
+            ws_dropped: 0,
             osc_enabled: config.osc.enabled,
-            osc_remote: format!("{}:{}", config.osc.remote_address, config.osc.remote_port),
+            osc_remote: config
+                .osc
+                .effective_destinations()
+                .iter()
+                .map(|d| format!("{}:{}", d.address, d.port))
+                .collect::<Vec<_>>()
+                .join(", "),
+            osc_links: Vec::new(),
             log_lines: Vec::new(),
             max_log_lines: config.tui.max_log_lines,
             show_timestamps: config.tui.show_timestamps,
             scroll_offset: 0,
             active_popup: None,
+            profile: None,
+            dispatch_errors: 0,
         }
     }
 
@@ -117,42 +148,46 @@ impl AppState {
     }
 
     fn push_info(&mut self, message: String) {
-        self.log_lines.push(LogEntry {
-            timestamp: now_hms(),
-            raw_line: String::new(),
-            result: message,
-        });
+        self.log_lines.push(LogEntry::new(String::new(), message));
     }
 }
 
 /// Run the full TUI application.
 pub fn run(
     config: Config,
-    mut router: bridge::Router,
-    ws_client_count: Option<websocket::ClientCount>,
-    mut ws_incoming_rx: tokio::sync::mpsc::Receiver<String>,
+    mut engine: Engine,
+    ws_clients: Option<Arc<websocket::ClientRegistry>>,
 ) -> Result<()> {
     let mut terminal = tui::init()?;
     let mut state = AppState::from_config(&config);
 
-    // Serial reader channel — initially no reader
-    let (serial_tx, serial_rx) = mpsc::channel::<String>();
-    let mut _serial_handle: Option<serial::SerialHandle> = None;
-
-    // Held keys for the watchdog
-    let mut held_keys: Vec<String> = Vec::new();
-    // Track which keys were refreshed this tick
-    let mut keys_seen_this_tick: Vec<String> = Vec::new();
-
-    let watchdog_interval = Duration::from_millis(config.protocol.watchdog_interval_ms);
-    let mut last_watchdog = Instant::now();
-
+    state.midi_recording = engine.router.bridge::<MidiBridge>().is_some_and(|m| m.is_recording());
     state.push_info("Mio started. Press [c] to connect serial, [?] for help.".into());
+    for bridge in engine.router.bridges() {
+        state.push_info(format!("Bridge {}: {}", bridge.name(), bridge.status()));
+    }
 
     loop {
         // --- Render ---
-        if let Some(count) = &ws_client_count {
-            state.ws_client_count = count.load(Ordering::Relaxed);
+        if let Some(registry) = &ws_clients {
+            state.ws_client_count = registry.count();
+            state.ws_dropped = registry.dropped();
+            if let Some(Popup::WsClients { clients, selected }) = &mut state.active_popup {
+                *clients = registry.snapshot();
+                *selected = (*selected).min(clients.len().saturating_sub(1));
+            }
+        }
+        if let Some(o) = engine.router.bridge::<OscBridge>() {
+            state.osc_links = o.links();
+            state.osc_remote = state.osc_links.iter().map(|l| l.addr.as_str()).collect::<Vec<_>>().join(", ");
+        }
+        state.serial_connected = engine.serial_connected();
+        state.ws_enabled = engine.router.bridge::<WsBridge>().is_some() && engine.router.enabled.websocket;
+        state.osc_enabled = engine.router.bridge::<OscBridge>().is_some() && engine.router.enabled.osc;
+        if config.profiles.is_empty() {
+            state.profile = None;
+        } else {
+            state.profile = Some(engine.routing.active_profile().to_string());
         }
         terminal.draw(|frame| layout::render(frame, &state))?;
 
@@ -176,10 +211,8 @@ pub fn run(
                 (Some(Popup::PortSelect { ports, selected }), TuiAction::Confirm) => {
                     if let Some(port) = ports.get(*selected) {
                         let port_name = port.name.clone();
-                        match serial::spawn_reader(&port_name, state.baud_rate, serial_tx.clone()) {
-                            Ok(handle) => {
-                                _serial_handle = Some(handle);
-                                state.serial_connected = true;
+                        match engine.connect_serial(&port_name, state.baud_rate) {
+                            Ok(()) => {
                                 state.serial_port_name = Some(port_name.clone());
                                 state.push_info(format!("Connected to {}", port_name));
                             }
@@ -201,7 +234,7 @@ pub fn run(
                     state.active_popup = Some(Popup::MidiSelect { ports, selected: new_sel });
                 }
                 (Some(Popup::MidiSelect { ports, selected }), TuiAction::Confirm) => {
-                    if let Some(midi_bridge) = &mut router.midi {
+                    if let Some(midi_bridge) = engine.router.bridge_mut::<MidiBridge>() {
                         if let Some(port) = ports.get(*selected) {
                             match midi_bridge.connect(port.index) {
                                 Ok(name) => {
@@ -216,6 +249,48 @@ pub fn run(
                     }
                     state.active_popup = None;
                 }
+                (Some(Popup::WsClients { clients, selected }), TuiAction::ScrollDown) => {
+                    let new_sel = (*selected + 1).min(clients.len().saturating_sub(1));
+                    let clients = clients.clone();
+                    state.active_popup = Some(Popup::WsClients { clients, selected: new_sel });
+                }
+                (Some(Popup::WsClients { clients, selected }), TuiAction::ScrollUp) => {
+                    let new_sel = selected.saturating_sub(1);
+                    let clients = clients.clone();
+                    state.active_popup = Some(Popup::WsClients { clients, selected: new_sel });
+                }
+                (Some(Popup::WsClients { clients, selected }), TuiAction::Kick) => {
+                    if let (Some(client), Some(registry)) = (clients.get(*selected), &ws_clients) {
+                        if registry.kick(client.id) {
+                            state.push_info(format!("Kicked WS client #{} ({})", client.id, client.peer));
+                        }
+                    }
+                }
+                (Some(Popup::WsClients { .. }), TuiAction::ShowClients) => {
+                    state.active_popup = None;
+                }
+                (Some(Popup::Profiles { names, selected }), TuiAction::ScrollDown) => {
+                    let new_sel = (*selected + 1).min(names.len().saturating_sub(1));
+                    let names = names.clone();
+                    state.active_popup = Some(Popup::Profiles { names, selected: new_sel });
+                }
+                (Some(Popup::Profiles { names, selected }), TuiAction::ScrollUp) => {
+                    let new_sel = selected.saturating_sub(1);
+                    let names = names.clone();
+                    state.active_popup = Some(Popup::Profiles { names, selected: new_sel });
+                }
+                (Some(Popup::Profiles { names, selected }), TuiAction::Confirm) => {
+                    if let Some(name) = names.get(*selected).cloned() {
+                        match engine.routing.switch_profile(&mut engine.router, &name) {
+                            Ok(()) => state.push_info(format!("Profile: {}", name)),
+                            Err(e) => state.push_info(format!("Profile switch failed: {}", e)),
+                        }
+                    }
+                    state.active_popup = None;
+                }
+                (Some(Popup::Profiles { .. }), TuiAction::ShowProfiles) => {
+                    state.active_popup = None;
+                }
                 (Some(_), TuiAction::DismissPopup) => {
                     state.active_popup = None;
                 }
@@ -226,13 +301,10 @@ pub fn run(
                 // No popup — normal key handling
                 (None, TuiAction::Quit) => break,
                 (None, TuiAction::ToggleConnect) => {
-                    if state.serial_connected {
+                    if engine.serial_connected() {
                         // Disconnect
-                        _serial_handle = None;
-                        state.serial_connected = false;
+                        engine.disconnect_serial();
                         let port = state.serial_port_name.take().unwrap_or_default();
-                        router.release_all_keys(&held_keys);
-                        held_keys.clear();
                         state.push_info(format!("Disconnected from {}", port));
                     } else {
                         // Show port selection popup
@@ -252,14 +324,14 @@ pub fn run(
                 (None, TuiAction::ToggleMidi) => {
                     if state.midi_port_name.is_some() {
                         // Disconnect MIDI
-                        if let Some(midi_bridge) = &mut router.midi {
+                        if let Some(midi_bridge) = engine.router.bridge_mut::<MidiBridge>() {
                             midi_bridge.disconnect();
                         }
                         let name = state.midi_port_name.take().unwrap_or_default();
                         state.push_info(format!("MIDI disconnected: {}", name));
                     } else {
                         // Show MIDI port selection popup
-                        if let Some(midi_bridge) = &router.midi {
+                        if let Some(midi_bridge) = engine.router.bridge::<MidiBridge>() {
                             let ports = midi_bridge.list_ports();
                             if ports.is_empty() {
                                 state.push_info("No MIDI output ports found".into());
@@ -271,6 +343,50 @@ pub fn run(
                         }
                     }
                 }
+                (None, TuiAction::ToggleRecord) => {
+                    if let Some(midi_bridge) = engine.router.bridge_mut::<MidiBridge>() {
+                        if midi_bridge.is_recording() {
+                            match midi_bridge.stop_recording() {
+                                Ok(Some((path, count))) => {
+                                    state.push_info(format!("MIDI recording saved: {} ({} events)", path.display(), count));
+                                }
+                                Ok(None) => {}
+                                Err(e) => {
+                                    state.push_info(format!("MIDI recording failed: {}", e));
+                                }
+                            }
+                        } else {
+                            let path = midi_recorder::default_recording_path();
+                            match midi_bridge.start_recording(path.clone()) {
+                                Ok(()) => state.push_info(format!("MIDI recording to {}", path.display())),
+                                Err(e) => state.push_info(format!("MIDI recording failed: {}", e)),
+                            }
+                        }
+                        state.midi_recording = midi_bridge.is_recording();
+                    } else if config.midi.simulate {
+                        state.push_info("MIDI output is simulated, nothing to record".into());
+                    } else {
+                        state.push_info("MIDI bridge is disabled".into());
+                    }
+                }
+                (None, TuiAction::ShowClients) => {
+                    match &ws_clients {
+                        Some(registry) => {
+                            let clients = registry.snapshot();
+                            state.active_popup = Some(Popup::WsClients { clients, selected: 0 });
+                        }
+                        None => state.push_info("WebSocket server is disabled".into()),
+                    }
+                }
+                (None, TuiAction::ShowProfiles) => {
+                    let names = engine.routing.profile_names();
+                    if names.len() > 1 {
+                        let selected = names.iter().position(|n| n == engine.routing.active_profile()).unwrap_or(0);
+                        state.active_popup = Some(Popup::Profiles { names, selected });
+                    } else {
+                        state.push_info("No profiles configured".into());
+                    }
+                }
                 (None, TuiAction::ShowHelp) => {
                     state.active_popup = Some(Popup::Help);
                 }
@@ -284,62 +400,32 @@ pub fn run(
             }
         }
 
-        // --- Process serial data ---
-        while let Ok(line) = serial_rx.try_recv() {
-            if let Some(cmd) = protocol::parse(&line) {
-                // Track held keys for watchdog
-                match &cmd {
-                    protocol::Command::KeyDown(key) => {
-                        if !held_keys.contains(key) {
-                            held_keys.push(key.clone());
-                        }
-                        keys_seen_this_tick.push(key.clone());
-                    }
-                    protocol::Command::KeyUp(key) => {
-                        held_keys.retain(|k| k != key);
-                    }
-                    _ => {}
+        // --- Process inputs, timers and the watchdog ---
+        for event in engine.step(Duration::ZERO) {
+            match event {
+                EngineEvent::Line { source, line, outcomes } => {
+                    state.dispatch_errors +=
+                        outcomes.iter().filter(|o| o.status == DispatchStatus::Error).count() as u64;
+                    state.push_log(LogEntry::dispatched(EngineEvent::display_line(source, &line), &outcomes));
+                    state.scroll_offset = 0;
                 }
-
-                let result = router.dispatch(&cmd);
-                state.push_log(LogEntry::new(line, result));
-                state.scroll_offset = 0;
-            }
-        }
-
-        // --- Process WebSocket incoming messages ---
-        while let Ok(line) = ws_incoming_rx.try_recv() {
-            if let Some(cmd) = protocol::parse(&line) {
-                let result = router.dispatch(&cmd);
-                state.push_log(LogEntry::new(format!("[ws] {}", line), result));
-                state.scroll_offset = 0;
-            }
-        }
-
-        // --- Watchdog tick ---
-        if last_watchdog.elapsed() >= watchdog_interval {
-            let stale_keys: Vec<String> = held_keys
-                .iter()
-                .filter(|k| !keys_seen_this_tick.contains(k))
-                .cloned()
-                .collect();
-
-            for key in &stale_keys {
-                if let Some(kb) = &mut router.keyboard {
-                    let _ = kb.key_up(key);
+                EngineEvent::Info(msg) => state.push_info(msg),
+                EngineEvent::SerialLost => {
+                    let port = state.serial_port_name.take().unwrap_or_default();
+                    state.push_info(format!("Serial port disconnected: {}", port));
                 }
-                held_keys.retain(|k| k != key);
-                state.push_info(format!("WATCHDOG: released {}", key));
             }
-
-            keys_seen_this_tick.clear();
-            last_watchdog = Instant::now();
         }
     }
 
     // --- Graceful shutdown ---
-    router.release_all_keys(&held_keys);
+    let events = engine.shutdown()?;
     tui::restore()?;
+    for event in events {
+        if let EngineEvent::Info(msg) = event {
+            println!("{}", msg);
+        }
+    }
 
     Ok(())
 }
@@ -2,6 +2,8 @@
 //!
 //! Must run on the main thread on macOS (CGEvent requirement).
 
+use super::Bridge;
+use crate::protocol::{Command, Source};
 use anyhow::{anyhow, Result};
 use enigo::{
     Direction::{Click, Press, Release},
@@ -38,6 +40,42 @@ impl KeyboardBridge {
     }
 }
 
+impl Bridge for KeyboardBridge {
+    fn name(&self) -> &str {
+        "keyboard"
+    }
+
+    fn handles(&self, cmd: &Command) -> bool {
+        matches!(cmd, Command::KeyDown(_) | Command::KeyUp(_) | Command::KeyTap(_) | Command::KeyType(_))
+    }
+
+    fn dispatch(&mut self, cmd: &Command, _source: Source) -> Result<String> {
+        match cmd {
+            Command::KeyDown(key) => match self.key_down(key) {
+                Ok(()) => Ok(format!("KEY {} ↓", key)),
+                Err(e) => Err(anyhow!("KEY {} ↓ ERROR: {}", key, e)),
+            },
+            Command::KeyUp(key) => match self.key_up(key) {
+                Ok(()) => Ok(format!("KEY {} ↑", key)),
+                Err(e) => Err(anyhow!("KEY {} ↑ ERROR: {}", key, e)),
+            },
+            Command::KeyTap(key) => match self.key_tap(key) {
+                Ok(()) => Ok(format!("KEY {} tap", key)),
+                Err(e) => Err(anyhow!("KEY {} tap ERROR: {}", key, e)),
+            },
+            Command::KeyType(text) => match self.key_type(text) {
+                Ok(()) => Ok(format!("KEY type \"{}\"", text)),
+                Err(e) => Err(anyhow!("KEY type ERROR: {}", e)),
+            },
+            _ => Err(anyhow!("KEY ERROR: not a key command")),
+        }
+    }
+
+    fn status(&self) -> String {
+        "ready".into()
+    }
+}
+
 /// Map a key name string to an enigo Key.
 fn map_key(name: &str) -> Result<Key> {
     // Single character — use Unicode key
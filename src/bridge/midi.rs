@@ -1,12 +1,17 @@
 //! MIDI output bridge using midir.
 
+use super::midi_recorder::MidiRecorder;
+use super::Bridge;
+use crate::protocol::{Command, Source};
 use anyhow::{anyhow, Result};
 use midir::{MidiOutput, MidiOutputConnection};
+use std::path::PathBuf;
 
 pub struct MidiBridge {
     output: MidiOutput,
     connection: Option<MidiOutputConnection>,
     connected_port_name: Option<String>,
+    recorder: Option<MidiRecorder>,
 }
 
 /// Information about an available MIDI output port.
@@ -16,6 +21,12 @@ pub struct MidiPortInfo {
     pub name: String,
 }
 
+impl Default for MidiBridge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MidiBridge {
     pub fn new() -> Self {
         let output = MidiOutput::new("Mio").expect("Failed to create MIDI output");
@@ -23,6 +34,7 @@ impl MidiBridge {
             output,
             connection: None,
             connected_port_name: None,
+            recorder: None,
         }
     }
 
@@ -76,7 +88,6 @@ impl MidiBridge {
         self.connected_port_name = None;
     }
 
-    #[allow(dead_code)]
     pub fn connected_port(&self) -> Option<&str> {
         self.connected_port_name.as_deref()
     }
@@ -105,7 +116,32 @@ impl MidiBridge {
         self.send_bytes(bytes)
     }
 
+    /// Start recording every outgoing message to a `.mid` file at `path`.
+    /// Messages are recorded even while no output port is connected.
+    /// Any recording already in progress is saved first.
+    pub fn start_recording(&mut self, path: PathBuf) -> Result<()> {
+        self.stop_recording()?;
+        self.recorder = Some(MidiRecorder::new(path));
+        Ok(())
+    }
+
+    /// Stop recording and write the file.
+    /// Returns the path written and the number of events, or None if not recording.
+    pub fn stop_recording(&mut self) -> Result<Option<(PathBuf, usize)>> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
     fn send_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(bytes);
+        }
         match &mut self.connection {
             Some(conn) => conn.send(bytes).map_err(|e| anyhow!("MIDI send error: {}", e)),
             None => Err(anyhow!("MIDI not connected")),
@@ -113,6 +149,60 @@ impl MidiBridge {
     }
 }
 
+impl Bridge for MidiBridge {
+    fn name(&self) -> &str {
+        "midi"
+    }
+
+    fn handles(&self, cmd: &Command) -> bool {
+        matches!(
+            cmd,
+            Command::MidiNoteOn { .. } | Command::MidiNoteOff { .. } | Command::MidiCc { .. } | Command::MidiRaw { .. }
+        )
+    }
+
+    fn dispatch(&mut self, cmd: &Command, _source: Source) -> Result<String> {
+        match cmd {
+            Command::MidiNoteOn { note, velocity, channel } => match self.note_on(*note, *velocity, *channel) {
+                Ok(()) => Ok(format!("MIDI ON note={} vel={} ch={}", note, velocity, channel)),
+                Err(e) => Err(anyhow!("MIDI ON ERROR: {}", e)),
+            },
+            Command::MidiNoteOff { note, velocity, channel } => match self.note_off(*note, *velocity, *channel) {
+                Ok(()) => Ok(format!("MIDI OFF note={} ch={}", note, channel)),
+                Err(e) => Err(anyhow!("MIDI OFF ERROR: {}", e)),
+            },
+            Command::MidiCc { controller, value, channel } => match self.cc(*controller, *value, *channel) {
+                Ok(()) => Ok(format!("MIDI CC {}={} ch={}", controller, value, channel)),
+                Err(e) => Err(anyhow!("MIDI CC ERROR: {}", e)),
+            },
+            Command::MidiRaw { bytes } => match self.raw(bytes) {
+                Ok(()) => Ok(format!("MIDI raw [{}, {}, {}]", bytes[0], bytes[1], bytes[2])),
+                Err(e) => Err(anyhow!("MIDI raw ERROR: {}", e)),
+            },
+            _ => Err(anyhow!("MIDI ERROR: not a MIDI command")),
+        }
+    }
+
+    fn status(&self) -> String {
+        let port = match self.connected_port() {
+            Some(name) => format!("connected to {}", name),
+            None => "not connected".to_string(),
+        };
+        if self.is_recording() {
+            format!("{}, recording", port)
+        } else {
+            port
+        }
+    }
+
+    /// Save a recording still in progress and close the port.
+    fn shutdown(&mut self) -> Result<()> {
+        self.stop_recording()?;
+        self.disconnect();
+        Ok(())
+    }
+}
+
 /// Standalone function to list MIDI ports (for --list-midi CLI flag).
 pub fn list_midi_ports() -> Vec<MidiPortInfo> {
     let bridge = MidiBridge::new();
@@ -0,0 +1,119 @@
+//! Standard MIDI File recorder for bridged MIDI output.
+//!
+//! Captures every message passed to the MIDI bridge with its arrival time
+//! and writes a Type-0 `.mid` file when recording stops.
+
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Ticks per quarter note written to the file header.
+const TICKS_PER_QUARTER: u16 = 480;
+
+/// Tempo written to the file, in microseconds per quarter note (120 BPM).
+const MICROS_PER_QUARTER: u32 = 500_000;
+
+/// An in-progress recording of MIDI messages.
+pub struct MidiRecorder {
+    path: PathBuf,
+    started: Instant,
+    events: Vec<(Duration, Vec<u8>)>,
+}
+
+impl MidiRecorder {
+    /// Start a new recording that will be saved to `path`.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            started: Instant::now(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Record a message, timestamped relative to the start of the recording.
+    pub fn record(&mut self, bytes: &[u8]) {
+        self.events.push((self.started.elapsed(), bytes.to_vec()));
+    }
+
+    /// Encode the recording and write it to disk.
+    /// Returns the path written and the number of events.
+    pub fn finish(self) -> Result<(PathBuf, usize)> {
+        let data = encode_smf(&self.events);
+        std::fs::write(&self.path, data)
+            .with_context(|| format!("Failed to write MIDI file: {}", self.path.display()))?;
+        Ok((self.path, self.events.len()))
+    }
+}
+
+/// Build a default recording path in the current directory, e.g. `mio-1718031234.mid`.
+pub fn default_recording_path() -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    PathBuf::from(format!("mio-{}.mid", secs))
+}
+
+/// Encode timestamped events as a Type-0 Standard MIDI File.
+pub fn encode_smf(events: &[(Duration, Vec<u8>)]) -> Vec<u8> {
+    let mut track = Vec::new();
+
+    // Tempo meta event at t=0 so the tick -> time mapping is explicit
+    write_vlq(&mut track, 0);
+    track.extend_from_slice(&[0xFF, 0x51, 0x03]);
+    track.extend_from_slice(&MICROS_PER_QUARTER.to_be_bytes()[1..]);
+
+    let micros_per_tick = MICROS_PER_QUARTER as f64 / TICKS_PER_QUARTER as f64;
+    let mut last_tick: u64 = 0;
+    for (at, bytes) in events {
+        if bytes.is_empty() {
+            continue;
+        }
+        let tick = (at.as_micros() as f64 / micros_per_tick).round() as u64;
+        let delta = tick.saturating_sub(last_tick);
+        last_tick = last_tick.max(tick);
+
+        write_vlq(&mut track, delta.min(0x0FFF_FFFF) as u32);
+        if bytes[0] == 0xF0 {
+            // SysEx: F0 <length> <data after F0>
+            track.push(0xF0);
+            write_vlq(&mut track, (bytes.len() - 1) as u32);
+            track.extend_from_slice(&bytes[1..]);
+        } else {
+            track.extend_from_slice(bytes);
+        }
+    }
+
+    // End of track
+    write_vlq(&mut track, 0);
+    track.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+
+    let mut out = Vec::with_capacity(22 + track.len());
+    out.extend_from_slice(b"MThd");
+    out.extend_from_slice(&6u32.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes()); // format 0
+    out.extend_from_slice(&1u16.to_be_bytes()); // one track
+    out.extend_from_slice(&TICKS_PER_QUARTER.to_be_bytes());
+    out.extend_from_slice(b"MTrk");
+    out.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    out.extend_from_slice(&track);
+    out
+}
+
+/// Write a variable-length quantity as used for SMF delta times.
+fn write_vlq(buf: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = [0u8; 4];
+    let mut n = 0;
+    loop {
+        bytes[n] = (value & 0x7F) as u8;
+        n += 1;
+        value >>= 7;
+        if value == 0 {
+            break;
+        }
+    }
+    for (i, b) in bytes[..n].iter().enumerate().rev() {
+        let continuation = if i > 0 { 0x80 } else { 0 };
+        buf.push(b | continuation);
+    }
+}
@@ -1,278 +1,377 @@
 //! Bridge router: dispatches parsed Commands to the appropriate bridge.
+//!
+//! Each output implements the `Bridge` trait; the router asks them in turn
+//! which one takes a command.
 
 pub mod keyboard;
 pub mod midi;
+pub mod midi_recorder;
 pub mod mouse;
 pub mod osc;
+pub mod osc_tcp;
+pub mod osc_types;
+pub mod oscquery;
+pub mod simulated;
 pub mod websocket;
+pub mod ws_api;
+pub mod ws_auth;
+pub mod ws_codec;
+pub mod ws_static;
+pub mod ws_stream;
+pub mod ws_topics;
 
-use crate::config::Config;
-use crate::protocol::Command;
+use crate::config::{Config, ProfileConfig};
+use crate::protocol::{Command, Source};
+use crate::routing;
 use anyhow::Result;
+use std::any::Any;
+use std::fmt;
+use std::time::{Duration, Instant};
 
-/// Central router that holds all enabled bridges and dispatches commands.
+/// An output commands can be sent to. The built-in bridges implement this,
+/// and programs embedding mio can add their own with [`Router::register`]:
+///
+/// ```no_run
+/// use anyhow::Result;
+/// use mio_bridge::bridge::{Bridge, Router};
+/// use mio_bridge::protocol::{Command, Source};
+///
+/// struct Lights;
+///
+/// impl Bridge for Lights {
+///     fn name(&self) -> &str {
+///         "lights"
+///     }
+///     fn handles(&self, cmd: &Command) -> bool {
+///         matches!(cmd, Command::OscMessage { address, .. } if address.starts_with("/lights/"))
+///     }
+///     fn dispatch(&mut self, cmd: &Command, _source: Source) -> Result<String> {
+///         Ok(format!("LIGHTS {:?}", cmd))
+///     }
+///     fn status(&self) -> String {
+///         "ready".into()
+///     }
+/// }
+///
+/// let mut router = Router::new(&mio_bridge::config::load(None)?)?;
+/// router.register(Box::new(Lights));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub trait Bridge: Any {
+    /// Short lowercase name for status displays. The built-in bridges use
+    /// the config section names, which profiles enable and disable them by.
+    fn name(&self) -> &str;
+
+    /// Whether this bridge takes `cmd`.
+    fn handles(&self, cmd: &Command) -> bool;
+
+    /// Carry out `cmd`, which `handles` accepted. `source` is where the line
+    /// came from. Returns what happened for the log; an error's message is
+    /// logged as is, so say what failed.
+    fn dispatch(&mut self, cmd: &Command, source: Source) -> Result<String>;
+
+    /// One line on the bridge's state, such as the connected port.
+    fn status(&self) -> String;
+
+    /// Periodic housekeeping, such as sending buffered output.
+    fn tick(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once before the program exits.
+    fn shutdown(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// What happened to one dispatched command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DispatchOutcome {
+    /// The bridge that took the command, or the built-in one that would
+    /// have when none did. The routing layer reports its own steps as
+    /// `filter`, `map`, `script` and `profile`.
+    pub bridge: String,
+    pub status: DispatchStatus,
+    /// What was done, or what failed and why.
+    pub detail: String,
+    /// How long the bridge took. Zero when no bridge ran.
+    pub latency: Duration,
+}
+
+/// Ordered from best to worst, so the worst of several is their `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DispatchStatus {
+    Ok,
+    /// No enabled bridge takes the command.
+    Disabled,
+    Error,
+}
+
+impl DispatchOutcome {
+    pub fn ok(bridge: &str, detail: impl Into<String>) -> Self {
+        Self::new(bridge, DispatchStatus::Ok, detail)
+    }
+
+    pub fn disabled(bridge: &str, detail: impl Into<String>) -> Self {
+        Self::new(bridge, DispatchStatus::Disabled, detail)
+    }
+
+    pub fn error(bridge: &str, detail: impl Into<String>) -> Self {
+        Self::new(bridge, DispatchStatus::Error, detail)
+    }
+
+    fn new(bridge: &str, status: DispatchStatus, detail: impl Into<String>) -> Self {
+        Self {
+            bridge: bridge.to_string(),
+            status,
+            detail: detail.into(),
+            latency: Duration::ZERO,
+        }
+    }
+
+    /// The log text for the outcomes of one line.
+    pub fn join(outcomes: &[DispatchOutcome]) -> String {
+        outcomes.iter().map(|o| o.to_string()).collect::<Vec<_>>().join(" | ")
+    }
+
+    /// The worst status among `outcomes`, Ok when there are none.
+    pub fn worst(outcomes: &[DispatchOutcome]) -> DispatchStatus {
+        outcomes.iter().map(|o| o.status).max().unwrap_or(DispatchStatus::Ok)
+    }
+}
+
+impl fmt::Display for DispatchOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            DispatchStatus::Disabled => write!(f, "{} (disabled)", self.detail),
+            DispatchStatus::Ok | DispatchStatus::Error => f.write_str(&self.detail),
+        }
+    }
+}
+
+/// Central router that holds the bridges and dispatches commands to them.
+#[derive(Default)]
 pub struct Router {
-    pub keyboard: Option<keyboard::KeyboardBridge>,
-    pub mouse: Option<mouse::MouseBridge>,
-    pub midi: Option<midi::MidiBridge>,
-    pub ws_tx: Option<tokio::sync::broadcast::Sender<String>>,
-    pub osc: Option<osc::OscBridge>,
+    /// Asked in order; the first enabled bridge that handles a command gets it.
+    bridges: Vec<Box<dyn Bridge>>,
+    /// Which bridges commands may reach, switched by profiles.
+    pub enabled: BridgeEnables,
+    /// What the simulated bridges were asked to do.
+    pub actions: simulated::ActionLog,
+}
+
+/// Which built-in bridges commands may reach. A bridge that is enabled here
+/// but was never created (or failed to start) still reports `(disabled)`.
+/// Bridges added with `Router::register` are always enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BridgeEnables {
+    pub keyboard: bool,
+    pub mouse: bool,
+    pub midi: bool,
+    pub websocket: bool,
+    pub osc: bool,
+}
+
+impl Default for BridgeEnables {
+    fn default() -> Self {
+        Self {
+            keyboard: true,
+            mouse: true,
+            midi: true,
+            websocket: true,
+            osc: true,
+        }
+    }
+}
+
+impl BridgeEnables {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            keyboard: config.keyboard.enabled,
+            mouse: config.mouse.enabled,
+            midi: config.midi.enabled,
+            websocket: config.websocket.enabled,
+            osc: config.osc.enabled,
+        }
+    }
+
+    /// These enables with a profile's overrides applied.
+    pub fn with_profile(self, profile: &ProfileConfig) -> Self {
+        Self {
+            keyboard: profile.keyboard.unwrap_or(self.keyboard),
+            mouse: profile.mouse.unwrap_or(self.mouse),
+            midi: profile.midi.unwrap_or(self.midi),
+            websocket: profile.websocket.unwrap_or(self.websocket),
+            osc: profile.osc.unwrap_or(self.osc),
+        }
+    }
+
+    /// Whether commands may reach the bridge called `name`.
+    fn allows(&self, name: &str) -> bool {
+        match name {
+            "keyboard" => self.keyboard,
+            "mouse" => self.mouse,
+            "midi" => self.midi,
+            "websocket" => self.websocket,
+            "osc" => self.osc,
+            _ => true,
+        }
+    }
 }
 
 impl Router {
-    /// Create a new router, initializing only the enabled bridges.
+    /// Create a new router with the built-in bridges enabled in the config
+    /// or in any profile.
     pub fn new(config: &Config) -> Result<Self> {
-        let keyboard = if config.keyboard.enabled {
+        let enabled = BridgeEnables::from_config(config);
+        let wanted = config
+            .profiles
+            .values()
+            .fold(enabled, |wanted, profile| BridgeEnables {
+                keyboard: wanted.keyboard || profile.keyboard == Some(true),
+                mouse: wanted.mouse || profile.mouse == Some(true),
+                midi: wanted.midi || profile.midi == Some(true),
+                websocket: wanted.websocket || profile.websocket == Some(true),
+                osc: wanted.osc || profile.osc == Some(true),
+            });
+        let mut router = Self {
+            enabled,
+            ..Self::default()
+        };
+        let log = router.actions.clone();
+
+        if wanted.keyboard && config.keyboard.simulate {
+            router.register(Box::new(simulated::SimulatedBridge::keyboard(log.clone())));
+        } else if wanted.keyboard {
             match keyboard::KeyboardBridge::new() {
-                Ok(kb) => Some(kb),
-                Err(e) => {
-                    eprintln!("[mio] Keyboard bridge unavailable: {}", e);
-                    None
-                }
+                Ok(kb) => router.register(Box::new(kb)),
+                Err(e) => eprintln!("[mio] Keyboard bridge unavailable: {}", e),
             }
-        } else {
-            None
-        };
+        }
 
-        let mouse = if config.mouse.enabled {
+        if wanted.mouse && config.mouse.simulate {
+            router.register(Box::new(simulated::SimulatedBridge::mouse(log.clone())));
+        } else if wanted.mouse {
             match mouse::MouseBridge::new() {
-                Ok(m) => Some(m),
-                Err(e) => {
-                    eprintln!("[mio] Mouse bridge unavailable: {}", e);
-                    None
-                }
+                Ok(m) => router.register(Box::new(m)),
+                Err(e) => eprintln!("[mio] Mouse bridge unavailable: {}", e),
             }
-        } else {
-            None
-        };
+        }
 
         // MIDI starts disconnected — user connects via TUI or auto_connect
-        let midi = if config.midi.enabled {
-            Some(midi::MidiBridge::new())
-        } else {
-            None
-        };
+        if wanted.midi && config.midi.simulate {
+            router.register(Box::new(simulated::SimulatedBridge::midi(log)));
+        } else if wanted.midi {
+            router.register(Box::new(midi::MidiBridge::new()));
+        }
 
-        let osc = if config.osc.enabled {
+        if wanted.osc {
             match osc::OscBridge::new(&config.osc) {
-                Ok(o) => Some(o),
-                Err(e) => {
-                    eprintln!("[mio] OSC bridge unavailable: {}", e);
-                    None
-                }
+                Ok(o) => router.register(Box::new(o)),
+                Err(e) => eprintln!("[mio] OSC bridge unavailable: {}", e),
             }
-        } else {
-            None
-        };
+        }
 
         // WebSocket broadcast channel — the WS server task will subscribe
-        let ws_tx = if config.websocket.enabled {
-            let (tx, _) = tokio::sync::broadcast::channel(256);
-            Some(tx)
-        } else {
-            None
-        };
+        if wanted.websocket {
+            router.register(Box::new(websocket::WsBridge::new(config.websocket.channel_capacity)));
+        }
 
-        Ok(Self {
-            keyboard,
-            mouse,
-            midi,
-            ws_tx,
-            osc,
-        })
+        Ok(router)
     }
 
-    /// Dispatch a command to the appropriate bridge.
-    /// Returns a human-readable description of what happened (for the log).
-    pub fn dispatch(&mut self, cmd: &Command) -> String {
-        match cmd {
-            // --- Keyboard ---
-            Command::KeyDown(key) => {
-                if let Some(kb) = &mut self.keyboard {
-                    match kb.key_down(key) {
-                        Ok(()) => format!("KEY {} ↓", key),
-                        Err(e) => format!("KEY {} ↓ ERROR: {}", key, e),
-                    }
-                } else {
-                    format!("KEY {} ↓ (disabled)", key)
-                }
-            }
-            Command::KeyUp(key) => {
-                if let Some(kb) = &mut self.keyboard {
-                    match kb.key_up(key) {
-                        Ok(()) => format!("KEY {} ↑", key),
-                        Err(e) => format!("KEY {} ↑ ERROR: {}", key, e),
-                    }
-                } else {
-                    format!("KEY {} ↑ (disabled)", key)
-                }
-            }
-            Command::KeyTap(key) => {
-                if let Some(kb) = &mut self.keyboard {
-                    match kb.key_tap(key) {
-                        Ok(()) => format!("KEY {} tap", key),
-                        Err(e) => format!("KEY {} tap ERROR: {}", key, e),
-                    }
-                } else {
-                    format!("KEY {} tap (disabled)", key)
-                }
-            }
-            Command::KeyType(text) => {
-                if let Some(kb) = &mut self.keyboard {
-                    match kb.key_type(text) {
-                        Ok(()) => format!("KEY type \"{}\"", text),
-                        Err(e) => format!("KEY type ERROR: {}", e),
-                    }
-                } else {
-                    format!("KEY type (disabled)")
-                }
-            }
+    /// A router with simulated keyboard, mouse and MIDI bridges only, which
+    /// record to `actions`. For tests.
+    pub fn simulated() -> Self {
+        let mut router = Self::default();
+        let log = router.actions.clone();
+        router.register(Box::new(simulated::SimulatedBridge::keyboard(log.clone())));
+        router.register(Box::new(simulated::SimulatedBridge::mouse(log.clone())));
+        router.register(Box::new(simulated::SimulatedBridge::midi(log)));
+        router
+    }
 
-            // --- Mouse ---
-            Command::MouseMove { x, y } => {
-                if let Some(m) = &mut self.mouse {
-                    match m.move_to(*x, *y) {
-                        Ok(()) => format!("MOUSE move ({}, {})", x, y),
-                        Err(e) => format!("MOUSE move ERROR: {}", e),
-                    }
-                } else {
-                    format!("MOUSE move (disabled)")
-                }
-            }
-            Command::MouseMoveRel { dx, dy } => {
-                if let Some(m) = &mut self.mouse {
-                    match m.move_relative(*dx, *dy) {
-                        Ok(()) => format!("MOUSE rel ({}, {})", dx, dy),
-                        Err(e) => format!("MOUSE rel ERROR: {}", e),
-                    }
-                } else {
-                    format!("MOUSE rel (disabled)")
-                }
-            }
-            Command::MouseClick(button) => {
-                if let Some(m) = &mut self.mouse {
-                    match m.click(button) {
-                        Ok(()) => format!("MOUSE click {}", button),
-                        Err(e) => format!("MOUSE click ERROR: {}", e),
-                    }
-                } else {
-                    format!("MOUSE click (disabled)")
-                }
-            }
-            Command::MouseDown(button) => {
-                if let Some(m) = &mut self.mouse {
-                    match m.button_down(button) {
-                        Ok(()) => format!("MOUSE {} ↓", button),
-                        Err(e) => format!("MOUSE {} ↓ ERROR: {}", button, e),
-                    }
-                } else {
-                    format!("MOUSE down (disabled)")
-                }
-            }
-            Command::MouseUp(button) => {
-                if let Some(m) = &mut self.mouse {
-                    match m.button_up(button) {
-                        Ok(()) => format!("MOUSE {} ↑", button),
-                        Err(e) => format!("MOUSE {} ↑ ERROR: {}", button, e),
-                    }
-                } else {
-                    format!("MOUSE up (disabled)")
-                }
-            }
-            Command::MouseScroll { x, y } => {
-                if let Some(m) = &mut self.mouse {
-                    match m.scroll(*x, *y) {
-                        Ok(()) => format!("MOUSE scroll ({}, {})", x, y),
-                        Err(e) => format!("MOUSE scroll ERROR: {}", e),
-                    }
-                } else {
-                    format!("MOUSE scroll (disabled)")
-                }
-            }
+    /// Add a bridge. Bridges added later are asked first, so a custom
+    /// bridge can take commands over from a built-in one.
+    pub fn register(&mut self, bridge: Box<dyn Bridge>) {
+        self.bridges.insert(0, bridge);
+    }
 
-            // --- MIDI ---
-            Command::MidiNoteOn { note, velocity, channel } => {
-                if let Some(m) = &mut self.midi {
-                    match m.note_on(*note, *velocity, *channel) {
-                        Ok(()) => format!("MIDI ON note={} vel={} ch={}", note, velocity, channel),
-                        Err(e) => format!("MIDI ON ERROR: {}", e),
-                    }
-                } else {
-                    format!("MIDI ON (disabled)")
-                }
-            }
-            Command::MidiNoteOff { note, velocity, channel } => {
-                if let Some(m) = &mut self.midi {
-                    match m.note_off(*note, *velocity, *channel) {
-                        Ok(()) => format!("MIDI OFF note={} ch={}", note, channel),
-                        Err(e) => format!("MIDI OFF ERROR: {}", e),
-                    }
-                } else {
-                    format!("MIDI OFF (disabled)")
-                }
-            }
-            Command::MidiCc { controller, value, channel } => {
-                if let Some(m) = &mut self.midi {
-                    match m.cc(*controller, *value, *channel) {
-                        Ok(()) => format!("MIDI CC {}={} ch={}", controller, value, channel),
-                        Err(e) => format!("MIDI CC ERROR: {}", e),
-                    }
-                } else {
-                    format!("MIDI CC (disabled)")
-                }
-            }
-            Command::MidiRaw { bytes } => {
-                if let Some(m) = &mut self.midi {
-                    match m.raw(bytes) {
-                        Ok(()) => format!("MIDI raw [{}, {}, {}]", bytes[0], bytes[1], bytes[2]),
-                        Err(e) => format!("MIDI raw ERROR: {}", e),
-                    }
-                } else {
-                    format!("MIDI raw (disabled)")
-                }
-            }
+    /// All bridges, in the order they are asked.
+    pub fn bridges(&self) -> impl Iterator<Item = &dyn Bridge> {
+        self.bridges.iter().map(|b| b.as_ref())
+    }
 
-            // --- WebSocket ---
-            Command::WsBroadcast { id, value } => {
-                if let Some(tx) = &self.ws_tx {
-                    let json = format!("{{\"id\":\"{}\",\"value\":{}}}", id, value);
-                    let count = tx.receiver_count();
-                    let _ = tx.send(json);
-                    format!("WS broadcast {}={} ({} clients)", id, value, count)
-                } else {
-                    format!("WS broadcast (disabled)")
-                }
-            }
-            Command::WsRaw(payload) => {
-                if let Some(tx) = &self.ws_tx {
-                    let count = tx.receiver_count();
-                    let _ = tx.send(payload.clone());
-                    format!("WS raw ({} clients)", count)
-                } else {
-                    format!("WS raw (disabled)")
-                }
-            }
+    /// The bridge of type `T`, if there is one.
+    pub fn bridge<T: Bridge>(&self) -> Option<&T> {
+        self.bridges.iter().find_map(|b| (b.as_ref() as &dyn Any).downcast_ref())
+    }
 
-            // --- OSC ---
-            Command::OscMessage { address, args } => {
-                if let Some(o) = &self.osc {
-                    match o.send(address, args) {
-                        Ok(()) => format!("OSC {} [{}]", address, args.join(", ")),
-                        Err(e) => format!("OSC ERROR: {}", e),
-                    }
-                } else {
-                    format!("OSC (disabled)")
-                }
-            }
+    /// The bridge of type `T`, if there is one.
+    pub fn bridge_mut<T: Bridge>(&mut self) -> Option<&mut T> {
+        self.bridges.iter_mut().find_map(|b| (b.as_mut() as &mut dyn Any).downcast_mut())
+    }
+
+    /// Dispatch a command to the first enabled bridge that handles it.
+    /// `source` is where the line came from, passed on to the bridge.
+    pub fn dispatch(&mut self, cmd: &Command, source: Source) -> DispatchOutcome {
+        // Profiles are switched by the routing layer before commands get here
+        if let Command::Profile(name) = cmd {
+            return DispatchOutcome::disabled("profile", format!("PROFILE {}", name));
         }
+        let enabled = self.enabled;
+        let Some(bridge) = self.bridges.iter_mut().find(|b| enabled.allows(b.name()) && b.handles(cmd)) else {
+            let (name, log_name) = bridge_name(cmd);
+            return DispatchOutcome::disabled(name, format!("{} {}", log_name, routing::command_line(cmd)));
+        };
+        let started = Instant::now();
+        let result = bridge.dispatch(cmd, source);
+        let mut outcome = match result {
+            Ok(detail) => DispatchOutcome::ok(bridge.name(), detail),
+            Err(e) => DispatchOutcome::error(bridge.name(), e.to_string()),
+        };
+        outcome.latency = started.elapsed();
+        outcome
     }
 
-    /// Release all currently held keys. Called on disconnect / shutdown.
+    /// Periodic housekeeping for bridges that buffer output (OSC bundles).
+    /// Returns error descriptions to log.
+    pub fn tick(&mut self) -> Vec<String> {
+        self.bridges.iter_mut().filter_map(|b| b.tick().err()).map(|e| e.to_string()).collect()
+    }
+
+    /// Shut every bridge down. Returns error descriptions to log.
+    pub fn shutdown(&mut self) -> Vec<String> {
+        self.bridges.iter_mut().filter_map(|b| b.shutdown().err()).map(|e| e.to_string()).collect()
+    }
+
+    /// Release all currently held keys, even on a bridge a profile has
+    /// disabled since. Called on disconnect / shutdown.
     pub fn release_all_keys(&mut self, held_keys: &[String]) {
-        if let Some(kb) = &mut self.keyboard {
-            for key in held_keys {
-                let _ = kb.key_up(key);
+        for key in held_keys {
+            let cmd = Command::KeyUp(key.clone());
+            if let Some(bridge) = self.bridges.iter_mut().find(|b| b.handles(&cmd)) {
+                let _ = bridge.dispatch(&cmd, Source::Serial);
             }
         }
     }
 }
+
+/// The built-in bridge a command goes to and its name in logs.
+fn bridge_name(cmd: &Command) -> (&'static str, &'static str) {
+    match cmd {
+        Command::KeyDown(_) | Command::KeyUp(_) | Command::KeyTap(_) | Command::KeyType(_) => ("keyboard", "KEY"),
+        Command::MouseMove { .. }
+        | Command::MouseMoveRel { .. }
+        | Command::MouseClick(_)
+        | Command::MouseDown(_)
+        | Command::MouseUp(_)
+        | Command::MouseScroll { .. } => ("mouse", "MOUSE"),
+        Command::MidiNoteOn { .. } | Command::MidiNoteOff { .. } | Command::MidiCc { .. } | Command::MidiRaw { .. } => {
+            ("midi", "MIDI")
+        }
+        Command::WsBroadcast { .. } | Command::WsRaw(_) => ("websocket", "WS"),
+        Command::OscMessage { .. } => ("osc", "OSC"),
+        Command::Profile(_) => ("profile", "PROFILE"),
+    }
+}
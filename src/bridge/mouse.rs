@@ -1,5 +1,7 @@
 //! Mouse simulation bridge using enigo.
 
+use super::Bridge;
+use crate::protocol::{Command, Source};
 use anyhow::{anyhow, Result};
 use enigo::{
     Button, Coordinate,
@@ -60,6 +62,58 @@ impl MouseBridge {
     }
 }
 
+impl Bridge for MouseBridge {
+    fn name(&self) -> &str {
+        "mouse"
+    }
+
+    fn handles(&self, cmd: &Command) -> bool {
+        matches!(
+            cmd,
+            Command::MouseMove { .. }
+                | Command::MouseMoveRel { .. }
+                | Command::MouseClick(_)
+                | Command::MouseDown(_)
+                | Command::MouseUp(_)
+                | Command::MouseScroll { .. }
+        )
+    }
+
+    fn dispatch(&mut self, cmd: &Command, _source: Source) -> Result<String> {
+        match cmd {
+            Command::MouseMove { x, y } => match self.move_to(*x, *y) {
+                Ok(()) => Ok(format!("MOUSE move ({}, {})", x, y)),
+                Err(e) => Err(anyhow!("MOUSE move ERROR: {}", e)),
+            },
+            Command::MouseMoveRel { dx, dy } => match self.move_relative(*dx, *dy) {
+                Ok(()) => Ok(format!("MOUSE rel ({}, {})", dx, dy)),
+                Err(e) => Err(anyhow!("MOUSE rel ERROR: {}", e)),
+            },
+            Command::MouseClick(button) => match self.click(button) {
+                Ok(()) => Ok(format!("MOUSE click {}", button)),
+                Err(e) => Err(anyhow!("MOUSE click ERROR: {}", e)),
+            },
+            Command::MouseDown(button) => match self.button_down(button) {
+                Ok(()) => Ok(format!("MOUSE {} ↓", button)),
+                Err(e) => Err(anyhow!("MOUSE {} ↓ ERROR: {}", button, e)),
+            },
+            Command::MouseUp(button) => match self.button_up(button) {
+                Ok(()) => Ok(format!("MOUSE {} ↑", button)),
+                Err(e) => Err(anyhow!("MOUSE {} ↑ ERROR: {}", button, e)),
+            },
+            Command::MouseScroll { x, y } => match self.scroll(*x, *y) {
+                Ok(()) => Ok(format!("MOUSE scroll ({}, {})", x, y)),
+                Err(e) => Err(anyhow!("MOUSE scroll ERROR: {}", e)),
+            },
+            _ => Err(anyhow!("MOUSE ERROR: not a mouse command")),
+        }
+    }
+
+    fn status(&self) -> String {
+        "ready".into()
+    }
+}
+
 fn map_button(name: &str) -> Result<Button> {
     match name.to_lowercase().as_str() {
         "left" => Ok(Button::Left),
@@ -1,62 +1,479 @@
-//! OSC (Open Sound Control) output bridge using rosc + UDP.
+//! OSC (Open Sound Control) bridge using rosc + UDP.
+//!
+//! Sends `osc:` commands to the configured remote address, and optionally
+//! receives OSC on the local port so controllers like TouchOSC or Max can
+//! drive mio the same way the serial device does.
 
-use crate::config::OscConfig;
-use anyhow::{Context, Result};
-use rosc::{OscMessage, OscPacket, OscType};
+use super::osc_tcp::{self, LinkState, TcpLink};
+use super::osc_types::{self, OscTypeSchema};
+use super::oscquery::{self, OscNamespace};
+use super::Bridge;
+use crate::config::{OscConfig, OscDestinationConfig, OscForward, OscReceiveConfig, OscSendMode, OscTransport};
+use crate::protocol::{Command, Source};
+use crate::routing::template;
+use anyhow::{anyhow, Context, Result};
+use rosc::{OscBundle, OscMessage, OscPacket, OscTime, OscType};
 use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant, SystemTime};
 
 pub struct OscBridge {
     socket: UdpSocket,
-    remote_addr: String,
+    local_addr: String,
+    destinations: Vec<Destination>,
+    schema: OscTypeSchema,
+    bundle_window: Duration,
+    bundle_delay: Duration,
+    /// When the first message of the current bundle window was queued.
+    window_started: Option<Instant>,
+    namespace: OscNamespace,
+}
+
+/// An output destination and the messages queued for its next bundle.
+struct Destination {
+    name: String,
+    addr: String,
+    prefixes: Vec<String>,
+    tcp: Option<TcpLink>,
+    pending: Vec<OscPacket>,
+}
+
+/// Status of one output destination, for display.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DestinationStatus {
+    pub name: String,
+    pub addr: String,
+    pub transport: OscTransport,
+    pub state: LinkState,
+}
+
+impl Destination {
+    fn new(config: &OscDestinationConfig) -> Self {
+        let addr = format!("{}:{}", config.address, config.port);
+        let tcp = match config.transport {
+            OscTransport::Tcp => Some(TcpLink::spawn(addr.clone())),
+            OscTransport::Udp => None,
+        };
+        Self {
+            name: config.name.clone(),
+            addr,
+            prefixes: config.prefixes.clone(),
+            tcp,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Whether this destination sends to the same place, the same way, as `config`.
+    fn same_link(&self, config: &OscDestinationConfig) -> bool {
+        let transport = if self.tcp.is_some() { OscTransport::Tcp } else { OscTransport::Udp };
+        self.addr == format!("{}:{}", config.address, config.port) && transport == config.transport
+    }
+
+    fn accepts(&self, address: &str) -> bool {
+        self.prefixes.is_empty() || self.prefixes.iter().any(|p| address.starts_with(p.as_str()))
+    }
+
+    /// Send an encoded packet over this destination's transport.
+    fn send_encoded(&self, socket: &UdpSocket, buf: Vec<u8>) -> Result<()> {
+        match &self.tcp {
+            Some(link) => link.send(buf),
+            None => socket.send_to(&buf, &self.addr).map(|_| ()).map_err(Into::into),
+        }
+        .with_context(|| format!("Failed to send OSC to {}", self.name))
+    }
+
+    fn status(&self) -> DestinationStatus {
+        let (transport, state) = match &self.tcp {
+            Some(link) => (OscTransport::Tcp, link.state()),
+            None => (OscTransport::Udp, LinkState::Ready),
+        };
+        DestinationStatus {
+            name: self.name.clone(),
+            addr: self.addr.clone(),
+            transport,
+            state,
+        }
+    }
+}
+
+/// A protocol line produced from an incoming OSC message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscInbound {
+    /// Parse and dispatch through the router (commands and WS broadcasts).
+    Dispatch(String),
+    /// Write to the connected serial device.
+    Serial(String),
+    /// A receive or decode error, for the log.
+    Error(String),
 }
 
 impl OscBridge {
     pub fn new(config: &OscConfig) -> Result<Self> {
         let local_addr = format!("{}:{}", config.local_address, config.local_port);
-        let remote_addr = format!("{}:{}", config.remote_address, config.remote_port);
+        let dest_configs = config.effective_destinations();
 
         let socket =
             UdpSocket::bind(&local_addr).with_context(|| format!("Failed to bind OSC socket to {}", local_addr))?;
-        socket.set_nonblocking(true)?;
+        // Blocking with a read timeout so the receiver thread can poll for shutdown
+        socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+        if dest_configs.iter().any(|d| d.mode == OscSendMode::Broadcast) {
+            socket.set_broadcast(true).context("Failed to enable OSC broadcast")?;
+        }
+        if dest_configs.iter().any(|d| d.mode == OscSendMode::Multicast) {
+            socket.set_multicast_loop_v4(true)?;
+        }
 
         Ok(Self {
             socket,
-            remote_addr,
+            local_addr,
+            destinations: dest_configs.iter().map(Destination::new).collect(),
+            schema: OscTypeSchema::new(&config.types)?,
+            bundle_window: Duration::from_millis(config.bundle_window_ms),
+            bundle_delay: Duration::from_millis(config.bundle_delay_ms),
+            window_started: None,
+            namespace: OscNamespace::new(config),
         })
     }
 
-    /// Send an OSC message to the configured remote address.
-    /// Args are attempted to parse as floats, falling back to strings.
-    pub fn send(&self, address: &str, args: &[String]) -> Result<()> {
-        let osc_args: Vec<OscType> = args
+    /// Send an OSC message to every destination whose prefixes match the address.
+    /// Args use their explicit type tag (`i:42`), else the `[osc.types]`
+    /// schema for the address, else a float/int/string guess.
+    /// With a bundle window configured the message is queued until `flush_due`.
+    pub fn send(&mut self, address: &str, args: &[String]) -> Result<()> {
+        let osc_args = osc_types::parse_args(args, self.schema.lookup(address))?;
+
+        let msg = OscMessage {
+            addr: address.to_string(),
+            args: osc_args,
+        };
+        self.namespace.observe(&msg, oscquery::ACCESS_READ);
+        let msg = OscPacket::Message(msg);
+
+        if !self.destinations.iter().any(|d| d.accepts(address)) {
+            return Err(anyhow!("No OSC destination for {}", address));
+        }
+
+        if !self.bundle_window.is_zero() {
+            for dest in self.destinations.iter_mut().filter(|d| d.accepts(address)) {
+                dest.pending.push(msg.clone());
+            }
+            self.window_started.get_or_insert_with(Instant::now);
+            return Ok(());
+        }
+
+        let buf = rosc::encoder::encode(&msg).context("Failed to encode OSC message")?;
+        let mut first_err = None;
+        for dest in self.destinations.iter().filter(|d| d.accepts(address)) {
+            if let Err(e) = dest.send_encoded(&self.socket, buf.clone()) {
+                first_err.get_or_insert(e);
+            }
+        }
+        first_err.map_or(Ok(()), Err)
+    }
+
+    /// Send queued bundles once the bundle window has elapsed.
+    /// Call this regularly from the event loop.
+    pub fn flush_due(&mut self) -> Result<()> {
+        match self.window_started {
+            Some(started) if started.elapsed() >= self.bundle_window => self.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Send all queued messages now, one bundle per destination.
+    pub fn flush(&mut self) -> Result<()> {
+        self.window_started = None;
+        let timetag = self.bundle_timetag();
+
+        let mut first_err = None;
+        for dest in &mut self.destinations {
+            if dest.pending.is_empty() {
+                continue;
+            }
+            let content = std::mem::take(&mut dest.pending);
+            let packet = OscPacket::Bundle(OscBundle { timetag, content });
+            let result = rosc::encoder::encode(&packet)
+                .context("Failed to encode OSC bundle")
+                .and_then(|buf| dest.send_encoded(&self.socket, buf));
+            if let Err(e) = result {
+                first_err.get_or_insert(e);
+            }
+        }
+        first_err.map_or(Ok(()), Err)
+    }
+
+    /// Replace the output destinations, e.g. when the profile changes.
+    /// Queued bundles go to the old destinations first. Destinations with an
+    /// unchanged address and transport keep their TCP connection.
+    pub fn set_destinations(&mut self, configs: &[OscDestinationConfig]) -> Result<()> {
+        let flushed = self.flush();
+        if configs.iter().any(|d| d.mode == OscSendMode::Broadcast) {
+            self.socket.set_broadcast(true).context("Failed to enable OSC broadcast")?;
+        }
+        if configs.iter().any(|d| d.mode == OscSendMode::Multicast) {
+            self.socket.set_multicast_loop_v4(true)?;
+        }
+        let mut old = std::mem::take(&mut self.destinations);
+        self.destinations = configs
             .iter()
-            .map(|a| {
-                // Try float first, then int, then string
-                if let Ok(f) = a.parse::<f32>() {
-                    OscType::Float(f)
-                } else if let Ok(i) = a.parse::<i32>() {
-                    OscType::Int(i)
-                } else {
-                    OscType::String(a.clone())
+            .map(|config| match old.iter().position(|d| d.same_link(config)) {
+                Some(i) => {
+                    let mut dest = old.swap_remove(i);
+                    dest.name = config.name.clone();
+                    dest.prefixes = config.prefixes.clone();
+                    dest
                 }
+                None => Destination::new(config),
             })
             .collect();
+        flushed
+    }
 
-        let msg = OscPacket::Message(OscMessage {
-            addr: address.to_string(),
-            args: osc_args,
+    /// The OSCQuery namespace of addresses sent and accepted.
+    pub fn namespace(&self) -> OscNamespace {
+        self.namespace.clone()
+    }
+
+    /// Per-destination transport and connection state.
+    pub fn links(&self) -> Vec<DestinationStatus> {
+        self.destinations.iter().map(Destination::status).collect()
+    }
+
+    /// Timetag for outgoing bundles: "immediately" or now + the configured delay.
+    fn bundle_timetag(&self) -> OscTime {
+        if self.bundle_delay.is_zero() {
+            return OscTime::from((0, 1));
+        }
+        OscTime::try_from(SystemTime::now() + self.bundle_delay).unwrap_or_else(|_| OscTime::from((0, 1)))
+    }
+
+    /// Spawn a reader thread for OSC arriving on the local port.
+    /// Each decoded message (bundles are flattened) is mapped through
+    /// `config` and sent to the event loop as an `OscInbound`, and so are
+    /// receive and decode errors.
+    pub fn spawn_receiver(
+        &self,
+        config: &OscReceiveConfig,
+        inbound_tx: mpsc::Sender<OscInbound>,
+    ) -> Result<OscReceiverHandle> {
+        let socket = self.socket.try_clone().context("Failed to clone OSC socket")?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let tcp_thread = if config.tcp {
+            let config = config.clone();
+            let inbound_tx = inbound_tx.clone();
+            let namespace = self.namespace.clone();
+            let handler = move |bytes: &[u8]| handle_packet(bytes, &config, &namespace, &inbound_tx);
+            Some(osc_tcp::spawn_listener(&self.local_addr, stop.clone(), handler)?)
+        } else {
+            None
+        };
+
+        let config = config.clone();
+        let namespace = self.namespace.clone();
+        let thread_stop = stop.clone();
+        let handle = std::thread::spawn(move || {
+            let mut buf = [0u8; rosc::decoder::MTU];
+
+            while !thread_stop.load(Ordering::Relaxed) {
+                let len = match socket.recv_from(&mut buf) {
+                    Ok((len, _peer)) => len,
+                    Err(ref e)
+                        if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut =>
+                    {
+                        continue;
+                    }
+                    Err(e) => {
+                        if inbound_tx.send(OscInbound::Error(format!("OSC receive error: {}", e))).is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                };
+
+                if !handle_packet(&buf[..len], &config, &namespace, &inbound_tx) {
+                    return;
+                }
+            }
         });
 
-        let buf = rosc::encoder::encode(&msg).context("Failed to encode OSC message")?;
-        self.socket
-            .send_to(&buf, &self.remote_addr)
-            .context("Failed to send OSC packet")?;
+        Ok(OscReceiverHandle {
+            stop,
+            _udp_thread: handle,
+            _tcp_thread: tcp_thread,
+        })
+    }
+}
+
+impl Bridge for OscBridge {
+    fn name(&self) -> &str {
+        "osc"
+    }
+
+    fn handles(&self, cmd: &Command) -> bool {
+        matches!(cmd, Command::OscMessage { .. })
+    }
+
+    fn dispatch(&mut self, cmd: &Command, _source: Source) -> Result<String> {
+        match cmd {
+            Command::OscMessage { address, args } => match self.send(address, args) {
+                Ok(()) => Ok(format!("OSC {} [{}]", address, args.join(", "))),
+                Err(e) => Err(anyhow!("OSC ERROR: {}", e)),
+            },
+            _ => Err(anyhow!("OSC ERROR: not an OSC command")),
+        }
+    }
+
+    fn status(&self) -> String {
+        self.links()
+            .iter()
+            .map(|link| format!("{} ({}): {:?}", link.name, link.addr, link.state))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn tick(&mut self) -> Result<()> {
+        self.flush_due().map_err(|e| anyhow!("OSC ERROR: {}", e))
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        self.flush().map_err(|e| anyhow!("OSC ERROR: {}", e))
+    }
+}
+
+impl Drop for OscBridge {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Handle for a running OSC receiver. Dropping this signals the threads to stop.
+pub struct OscReceiverHandle {
+    stop: Arc<AtomicBool>,
+    _udp_thread: std::thread::JoinHandle<()>,
+    _tcp_thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Drop for OscReceiverHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Decode one packet and forward every mapped message.
+/// Returns false once the event loop has gone away.
+fn handle_packet(
+    bytes: &[u8],
+    config: &OscReceiveConfig,
+    namespace: &OscNamespace,
+    inbound_tx: &mpsc::Sender<OscInbound>,
+) -> bool {
+    let packet = match rosc::decoder::decode_udp(bytes) {
+        Ok((_, packet)) => packet,
+        Err(e) => return inbound_tx.send(OscInbound::Error(format!("OSC decode error: {}", e))).is_ok(),
+    };
 
-        Ok(())
+    let mut messages = Vec::new();
+    flatten_packet(packet, &mut messages);
+    for msg in &messages {
+        if let Some(inbound) = route_incoming(config, msg) {
+            namespace.observe(msg, oscquery::ACCESS_WRITE);
+            if inbound_tx.send(inbound).is_err() {
+                return false;
+            }
+        }
     }
+    true
+}
+
+/// Collect every message in a packet, descending into nested bundles.
+fn flatten_packet(packet: OscPacket, out: &mut Vec<OscMessage>) {
+    match packet {
+        OscPacket::Message(msg) => out.push(msg),
+        OscPacket::Bundle(bundle) => {
+            for inner in bundle.content {
+                flatten_packet(inner, out);
+            }
+        }
+    }
+}
+
+/// Map an incoming OSC message to a protocol line and destination.
+/// Explicit routes win, then the command prefix, then `unmapped`.
+pub fn route_incoming(config: &OscReceiveConfig, msg: &OscMessage) -> Option<OscInbound> {
+    let args: Vec<String> = msg.args.iter().filter_map(arg_to_string).collect();
+
+    if let Some(route) = config.routes.iter().find(|r| address_matches(&r.address, &msg.addr)) {
+        return forward(route.to, route.line.as_deref(), &msg.addr, &args);
+    }
+
+    if let Some(line) = command_line(&config.command_prefix, &msg.addr, &args) {
+        return Some(OscInbound::Dispatch(line));
+    }
+
+    forward(config.unmapped, None, &msg.addr, &args)
+}
+
+/// Translate `<prefix>/<cmd>/<sub> args...` into `cmd:sub,args...`.
+/// `<prefix>/line "key:tap,a"` passes its first argument through verbatim.
+fn command_line(prefix: &str, address: &str, args: &[String]) -> Option<String> {
+    if prefix.is_empty() {
+        return None;
+    }
+    let rest = address.strip_prefix(prefix)?.strip_prefix('/')?;
+    if rest == "line" {
+        return args.first().cloned();
+    }
+
+    let (cmd, sub) = rest.split_once('/')?;
+    if cmd.is_empty() || sub.is_empty() {
+        return None;
+    }
+    let mut line = format!("{}:{}", cmd, sub);
+    for arg in args {
+        line.push(',');
+        line.push_str(arg);
+    }
+    Some(line)
+}
+
+fn forward(to: OscForward, template: Option<&str>, address: &str, args: &[String]) -> Option<OscInbound> {
+    let default_template = match to {
+        OscForward::Command => "{args}",
+        OscForward::Ws => "ws:{address},{args}",
+        OscForward::Serial => "osc:{address},{args}",
+        OscForward::Ignore => return None,
+    };
+    let line = template::render(template.unwrap_or(default_template), args, |name| {
+        (name == "address").then(|| address.to_string())
+    });
+    match to {
+        OscForward::Serial => Some(OscInbound::Serial(line)),
+        _ => Some(OscInbound::Dispatch(line)),
+    }
+}
+
+/// Match an address against an exact pattern or a `prefix*` pattern.
+fn address_matches(pattern: &str, address: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => address.starts_with(prefix),
+        None => pattern == address,
+    }
+}
 
-    #[allow(dead_code)]
-    pub fn remote_addr(&self) -> &str {
-        &self.remote_addr
+/// Render an OSC argument the way it would be written in a protocol line.
+fn arg_to_string(arg: &OscType) -> Option<String> {
+    match arg {
+        OscType::Int(i) => Some(i.to_string()),
+        OscType::Long(l) => Some(l.to_string()),
+        OscType::Float(f) => Some(f.to_string()),
+        OscType::Double(d) => Some(d.to_string()),
+        OscType::String(s) => Some(s.clone()),
+        OscType::Char(c) => Some(c.to_string()),
+        OscType::Bool(b) => Some(if *b { "1".into() } else { "0".into() }),
+        OscType::Midi(m) => Some(format!("{},{},{}", m.status, m.data1, m.data2)),
+        OscType::Nil | OscType::Inf => None,
+        OscType::Blob(_) | OscType::Time(_) | OscType::Color(_) | OscType::Array(_) => None,
     }
 }
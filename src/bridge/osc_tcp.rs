@@ -0,0 +1,279 @@
+//! OSC 1.1 over TCP with SLIP framing.
+//!
+//! Outgoing TCP destinations each get a writer thread that owns the
+//! connection and reconnects with backoff, so a dead remote never blocks
+//! the event loop. The optional local listener accepts TCP clients and
+//! hands every decoded packet to the receive mapping.
+
+use anyhow::{anyhow, Context, Result};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+/// Largest SLIP frame accepted, well beyond any OSC packet mio handles.
+/// Longer frames are dropped, so a peer that never sends END cannot grow
+/// the buffer without bound.
+pub const MAX_FRAME_LEN: usize = 64 * 1024;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Connection state of an OSC destination, shown in the TUI.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkState {
+    /// Connectionless transport (UDP), always ready.
+    Ready,
+    Connecting,
+    Connected,
+    /// Last connection attempt or write failed; retrying.
+    Disconnected(String),
+}
+
+impl LinkState {
+    pub fn is_up(&self) -> bool {
+        matches!(self, LinkState::Ready | LinkState::Connected)
+    }
+}
+
+/// Encode one packet as a double-END SLIP frame.
+pub fn slip_encode(packet: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(packet.len() + 4);
+    out.push(SLIP_END);
+    for &b in packet {
+        match b {
+            SLIP_END => out.extend_from_slice(&[SLIP_ESC, SLIP_ESC_END]),
+            SLIP_ESC => out.extend_from_slice(&[SLIP_ESC, SLIP_ESC_ESC]),
+            _ => out.push(b),
+        }
+    }
+    out.push(SLIP_END);
+    out
+}
+
+/// Incremental SLIP decoder for a byte stream.
+#[derive(Default)]
+pub struct SlipDecoder {
+    frame: Vec<u8>,
+    escaped: bool,
+    /// The current frame went over `MAX_FRAME_LEN`; skip to the next END.
+    oversized: bool,
+}
+
+impl SlipDecoder {
+    /// Feed bytes, returning every complete (non-empty) frame.
+    /// Frames longer than `MAX_FRAME_LEN` are dropped.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+        for &b in bytes {
+            if self.escaped {
+                self.escaped = false;
+                match b {
+                    SLIP_ESC_END => self.add(SLIP_END),
+                    SLIP_ESC_ESC => self.add(SLIP_ESC),
+                    // Protocol violation: keep the byte as-is
+                    other => self.add(other),
+                }
+                continue;
+            }
+            match b {
+                SLIP_END => {
+                    if self.oversized {
+                        self.oversized = false;
+                    } else if !self.frame.is_empty() {
+                        frames.push(std::mem::take(&mut self.frame));
+                    }
+                }
+                SLIP_ESC => self.escaped = true,
+                other => self.add(other),
+            }
+        }
+        frames
+    }
+
+    fn add(&mut self, b: u8) {
+        if self.oversized {
+            return;
+        }
+        if self.frame.len() >= MAX_FRAME_LEN {
+            self.frame = Vec::new();
+            self.oversized = true;
+            return;
+        }
+        self.frame.push(b);
+    }
+}
+
+/// An outgoing TCP connection to one destination.
+pub struct TcpLink {
+    tx: mpsc::Sender<Vec<u8>>,
+    state: Arc<Mutex<LinkState>>,
+    _thread: std::thread::JoinHandle<()>,
+}
+
+impl TcpLink {
+    /// Start the writer thread for `addr` (`host:port`). Connection happens
+    /// in the background; packets sent while disconnected are dropped.
+    pub fn spawn(addr: String) -> Self {
+        let (tx, rx) = mpsc::channel::<Vec<u8>>();
+        let state = Arc::new(Mutex::new(LinkState::Connecting));
+        let thread_state = state.clone();
+
+        let handle = std::thread::spawn(move || run_link(&addr, &rx, &thread_state));
+
+        Self {
+            tx,
+            state,
+            _thread: handle,
+        }
+    }
+
+    /// Queue an encoded OSC packet for sending.
+    pub fn send(&self, packet: Vec<u8>) -> Result<()> {
+        let state = self.state();
+        if !state.is_up() {
+            return Err(anyhow!("TCP link not connected ({:?})", state));
+        }
+        self.tx.send(packet).map_err(|_| anyhow!("TCP link thread has stopped"))
+    }
+
+    pub fn state(&self) -> LinkState {
+        self.state.lock().map(|s| s.clone()).unwrap_or(LinkState::Connecting)
+    }
+}
+
+fn set_state(state: &Mutex<LinkState>, new: LinkState) {
+    if let Ok(mut s) = state.lock() {
+        *s = new;
+    }
+}
+
+/// Writer thread: connect, write frames, reconnect with backoff on failure.
+/// Exits when the owning `TcpLink` (and so the sender) is dropped.
+fn run_link(addr: &str, rx: &mpsc::Receiver<Vec<u8>>, state: &Mutex<LinkState>) {
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        set_state(state, LinkState::Connecting);
+        let mut stream = match connect(addr) {
+            Ok(stream) => {
+                backoff = MIN_BACKOFF;
+                set_state(state, LinkState::Connected);
+                stream
+            }
+            Err(e) => {
+                set_state(state, LinkState::Disconnected(e.to_string()));
+                // Sleep out the backoff, discarding anything queued meanwhile
+                match rx.recv_timeout(backoff) {
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                    _ => while rx.try_recv().is_ok() {},
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue;
+            }
+        };
+
+        loop {
+            match rx.recv_timeout(Duration::from_millis(500)) {
+                Ok(packet) => {
+                    if let Err(e) = stream.write_all(&slip_encode(&packet)) {
+                        set_state(state, LinkState::Disconnected(e.to_string()));
+                        break;
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if peer_closed(&mut stream) {
+                        set_state(state, LinkState::Disconnected("connection closed".into()));
+                        break;
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+}
+
+fn connect(addr: &str) -> Result<TcpStream> {
+    let sock_addr: SocketAddr = addr
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {}", addr))?
+        .next()
+        .ok_or_else(|| anyhow!("No address for {}", addr))?;
+    let stream = TcpStream::connect_timeout(&sock_addr, CONNECT_TIMEOUT)?;
+    stream.set_nodelay(true)?;
+    stream.set_write_timeout(Some(Duration::from_secs(2)))?;
+    Ok(stream)
+}
+
+/// Detect a remote close without blocking. Incoming bytes are discarded.
+fn peer_closed(stream: &mut TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return true;
+    }
+    let mut buf = [0u8; 512];
+    let closed = match stream.read(&mut buf) {
+        Ok(0) => true,
+        Ok(_) => false,
+        Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => false,
+        Err(_) => true,
+    };
+    closed || stream.set_nonblocking(false).is_err()
+}
+
+/// Accept OSC-over-TCP clients on `addr`, calling `on_packet` for every
+/// SLIP frame received. Runs until `stop` is set.
+pub fn spawn_listener<F>(addr: &str, stop: Arc<AtomicBool>, on_packet: F) -> Result<std::thread::JoinHandle<()>>
+where
+    F: Fn(&[u8]) -> bool + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(addr).with_context(|| format!("Failed to bind OSC TCP listener to {}", addr))?;
+    listener.set_nonblocking(true)?;
+    let on_packet = Arc::new(on_packet);
+
+    Ok(std::thread::spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _peer)) => {
+                    let stop = stop.clone();
+                    let on_packet = on_packet.clone();
+                    std::thread::spawn(move || read_client(stream, &stop, &*on_packet));
+                }
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                Err(_) => std::thread::sleep(Duration::from_millis(100)),
+            }
+        }
+    }))
+}
+
+fn read_client(mut stream: TcpStream, stop: &AtomicBool, on_packet: &(dyn Fn(&[u8]) -> bool + Send + Sync)) {
+    if stream.set_nonblocking(false).is_err() || stream.set_read_timeout(Some(Duration::from_millis(200))).is_err() {
+        return;
+    }
+    let mut decoder = SlipDecoder::default();
+    let mut buf = [0u8; 4096];
+
+    while !stop.load(Ordering::Relaxed) {
+        match stream.read(&mut buf) {
+            Ok(0) => return,
+            Ok(n) => {
+                for frame in decoder.push(&buf[..n]) {
+                    if !on_packet(&frame) {
+                        return;
+                    }
+                }
+            }
+            Err(ref e)
+                if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut => {}
+            Err(_) => return,
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! OSC argument typing for outgoing messages.
+//!
+//! Arguments can carry an explicit type tag: `i:42`, `h:9000000000`, `f:0.5`,
+//! `d:0.25`, `s:hello`, `c:x`, `b:<base64>`, `m:<status>:<data1>:<data2>`
+//! (or `m:<port>:<status>:<data1>:<data2>`), `t:now` / `t:+0.5` / `t:<unix secs>`,
+//! and the bare tags `T`, `F`, `N` and `I`.
+//!
+//! Other arguments follow the per-address schema from `[osc.types]` if one
+//! matches, so an address typed `"s"` sends a bare `T` as the string "T".
+//! Arguments without a schema take the bare tags, and otherwise fall back to
+//! guessing (float, then int, then string).
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use rosc::{OscMidiMessage, OscTime, OscType};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Per-address type strings, e.g. `"/layer1/clip" = "ii"`.
+/// Keys ending in `*` match any address with that prefix.
+#[derive(Debug, Clone, Default)]
+pub struct OscTypeSchema {
+    exact: BTreeMap<String, String>,
+    prefixes: Vec<(String, String)>,
+}
+
+/// Type characters a schema may use.
+const SCHEMA_TAGS: &str = "ihfdscbmtTFNI";
+
+impl OscTypeSchema {
+    /// Build the schema, rejecting unknown type characters.
+    pub fn new(types: &BTreeMap<String, String>) -> Result<Self> {
+        let mut exact = BTreeMap::new();
+        let mut prefixes = Vec::new();
+        for (pattern, tags) in types {
+            if let Some(tag) = tags.chars().find(|c| !SCHEMA_TAGS.contains(*c)) {
+                return Err(anyhow!("osc.types: unknown OSC type tag '{}' for {}", tag, pattern));
+            }
+            match pattern.strip_suffix('*') {
+                Some(prefix) => prefixes.push((prefix.to_string(), tags.clone())),
+                None => {
+                    exact.insert(pattern.clone(), tags.clone());
+                }
+            }
+        }
+        // Longest prefix wins
+        prefixes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        Ok(Self { exact, prefixes })
+    }
+
+    /// Find the type string for an address, if any.
+    pub fn lookup(&self, address: &str) -> Option<&str> {
+        if let Some(tags) = self.exact.get(address) {
+            return Some(tags);
+        }
+        self.prefixes
+            .iter()
+            .find(|(prefix, _)| address.starts_with(prefix.as_str()))
+            .map(|(_, tags)| tags.as_str())
+    }
+}
+
+/// Convert protocol arguments to OSC arguments.
+/// `schema` is the type string for the address (one character per argument).
+pub fn parse_args(args: &[String], schema: Option<&str>) -> Result<Vec<OscType>> {
+    let mut hints = schema.map(|s| s.chars());
+    args.iter()
+        .enumerate()
+        .map(|(i, raw)| {
+            let hint = hints.as_mut().and_then(|h| h.next());
+            parse_arg(raw, hint).with_context(|| format!("OSC argument {} ({:?})", i + 1, raw))
+        })
+        .collect()
+}
+
+/// Convert a single argument, honouring an explicit `x:` tag first, then
+/// `hint`, then the bare tags.
+pub fn parse_arg(raw: &str, hint: Option<char>) -> Result<OscType> {
+    if let Some((tag, value)) = split_tag(raw) {
+        return parse_typed(tag, value);
+    }
+    if let Some(tag) = hint {
+        return parse_typed(tag, raw);
+    }
+
+    match raw {
+        "T" => Ok(OscType::Bool(true)),
+        "F" => Ok(OscType::Bool(false)),
+        "N" => Ok(OscType::Nil),
+        "I" => Ok(OscType::Inf),
+        _ => Ok(guess(raw)),
+    }
+}
+
+/// Split `x:value` where `x` is a known value tag.
+fn split_tag(raw: &str) -> Option<(char, &str)> {
+    let mut chars = raw.chars();
+    let tag = chars.next()?;
+    let rest = chars.as_str().strip_prefix(':')?;
+    "ihfdscbmt".contains(tag).then_some((tag, rest))
+}
+
+fn parse_typed(tag: char, raw_value: &str) -> Result<OscType> {
+    let value = raw_value.trim();
+    match tag {
+        'i' => Ok(OscType::Int(value.parse().map_err(|_| anyhow!("invalid int"))?)),
+        'h' => Ok(OscType::Long(value.parse().map_err(|_| anyhow!("invalid int64"))?)),
+        'f' => Ok(OscType::Float(value.parse().map_err(|_| anyhow!("invalid float"))?)),
+        'd' => Ok(OscType::Double(value.parse().map_err(|_| anyhow!("invalid double"))?)),
+        's' => Ok(OscType::String(raw_value.to_string())),
+        'c' => {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(OscType::Char(c)),
+                _ => Err(anyhow!("invalid char")),
+            }
+        }
+        'b' => {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(value)
+                .map_err(|e| anyhow!("invalid base64 blob: {}", e))?;
+            Ok(OscType::Blob(bytes))
+        }
+        'm' => parse_midi(value),
+        't' => parse_time(value),
+        'T' | 'F' => parse_bool(value),
+        'N' => Ok(OscType::Nil),
+        'I' => Ok(OscType::Inf),
+        _ => Err(anyhow!("unknown OSC type tag '{}'", tag)),
+    }
+}
+
+/// The original float-first guess, kept for untagged, unschema'd arguments.
+fn guess(raw: &str) -> OscType {
+    if let Ok(f) = raw.parse::<f32>() {
+        OscType::Float(f)
+    } else if let Ok(i) = raw.parse::<i32>() {
+        OscType::Int(i)
+    } else {
+        OscType::String(raw.to_string())
+    }
+}
+
+fn parse_bool(value: &str) -> Result<OscType> {
+    match value.to_lowercase().as_str() {
+        "1" | "t" | "true" | "on" | "yes" => Ok(OscType::Bool(true)),
+        "0" | "f" | "false" | "off" | "no" => Ok(OscType::Bool(false)),
+        _ => Err(anyhow!("invalid bool")),
+    }
+}
+
+/// `status:data1:data2` or `port:status:data1:data2`.
+fn parse_midi(value: &str) -> Result<OscType> {
+    let bytes: Vec<u8> = value
+        .split(':')
+        .map(|b| b.trim().parse::<u8>())
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| anyhow!("invalid MIDI bytes"))?;
+    let (port, status, data1, data2) = match bytes.as_slice() {
+        [status, data1, data2] => (0, *status, *data1, *data2),
+        [port, status, data1, data2] => (*port, *status, *data1, *data2),
+        _ => return Err(anyhow!("MIDI argument needs 3 or 4 bytes")),
+    };
+    Ok(OscType::Midi(OscMidiMessage {
+        port,
+        status,
+        data1,
+        data2,
+    }))
+}
+
+/// `now`, `+<seconds>` relative to now, or absolute `<unix seconds>`.
+fn parse_time(value: &str) -> Result<OscType> {
+    let time = if value == "now" {
+        SystemTime::now()
+    } else if let Some(offset) = value.strip_prefix('+') {
+        let secs: f64 = offset.parse().map_err(|_| anyhow!("invalid time offset"))?;
+        SystemTime::now() + Duration::try_from_secs_f64(secs).map_err(|_| anyhow!("invalid time offset"))?
+    } else {
+        let secs: f64 = value.parse().map_err(|_| anyhow!("invalid timetag"))?;
+        UNIX_EPOCH + Duration::try_from_secs_f64(secs).map_err(|_| anyhow!("invalid timetag"))?
+    };
+    let tag = OscTime::try_from(time).map_err(|_| anyhow!("timetag out of range"))?;
+    Ok(OscType::Time(tag))
+}
@@ -0,0 +1,235 @@
+//! OSCQuery namespace: every OSC address mio sends or accepts.
+//!
+//! The namespace is filled from the receive mapping at startup and from
+//! observed traffic afterwards, and served as OSCQuery JSON by the HTTP side
+//! of the WebSocket server. WebSocket clients can `LISTEN` to addresses to
+//! receive value updates as binary OSC packets.
+
+use crate::config::{OscConfig, OscForward};
+use rosc::{OscMessage, OscPacket, OscType};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// OSCQuery ACCESS bits.
+pub const ACCESS_READ: u8 = 1;
+pub const ACCESS_WRITE: u8 = 2;
+
+/// A single OSC method in the namespace.
+#[derive(Debug, Clone, Default)]
+struct Node {
+    types: Option<String>,
+    value: Vec<OscType>,
+    access: u8,
+}
+
+/// Shared, cloneable handle to the namespace.
+#[derive(Clone)]
+pub struct OscNamespace {
+    nodes: Arc<Mutex<BTreeMap<String, Node>>>,
+    host_info: Value,
+    updates: broadcast::Sender<(String, Vec<u8>)>,
+}
+
+impl OscNamespace {
+    /// Create the namespace, declaring the addresses the receive mapping accepts.
+    pub fn new(config: &OscConfig) -> Self {
+        let transport = if config.receive.tcp { "TCP" } else { "UDP" };
+        let mut host_info = json!({
+            "NAME": "mio",
+            "OSC_PORT": config.local_port,
+            "OSC_TRANSPORT": transport,
+            "EXTENSIONS": {
+                "ACCESS": true,
+                "VALUE": true,
+                "TYPE": true,
+                "LISTEN": true,
+            },
+        });
+        if config.local_address != "0.0.0.0" {
+            host_info["OSC_IP"] = json!(config.local_address);
+        }
+
+        let (updates, _) = broadcast::channel(256);
+        let namespace = Self {
+            nodes: Arc::new(Mutex::new(BTreeMap::new())),
+            host_info,
+            updates,
+        };
+
+        let receive = &config.receive;
+        if receive.enabled {
+            for route in &receive.routes {
+                if route.to != OscForward::Ignore && !route.address.ends_with('*') {
+                    namespace.declare(&route.address, None, ACCESS_WRITE);
+                }
+            }
+            if !receive.command_prefix.is_empty() {
+                for (path, types) in COMMAND_METHODS {
+                    namespace.declare(&format!("{}{}", receive.command_prefix, path), Some(types), ACCESS_WRITE);
+                }
+            }
+        }
+
+        namespace
+    }
+
+    /// Add an address with known access, without a value.
+    pub fn declare(&self, address: &str, types: Option<&str>, access: u8) {
+        if let Ok(mut nodes) = self.nodes.lock() {
+            let node = nodes.entry(address.to_string()).or_default();
+            node.access |= access;
+            if let Some(types) = types {
+                node.types = Some(types.to_string());
+            }
+        }
+    }
+
+    /// Record a message mio sent (readable) or accepted (writable).
+    pub fn observe(&self, msg: &OscMessage, access: u8) {
+        if let Ok(mut nodes) = self.nodes.lock() {
+            let node = nodes.entry(msg.addr.clone()).or_default();
+            node.access |= access;
+            node.types = Some(msg.args.iter().map(type_tag).collect());
+            node.value = msg.args.clone();
+        }
+
+        if self.updates.receiver_count() > 0 {
+            if let Ok(buf) = rosc::encoder::encode(&OscPacket::Message(msg.clone())) {
+                let _ = self.updates.send((msg.addr.clone(), buf));
+            }
+        }
+    }
+
+    /// Subscribe to encoded OSC packets for every observed message.
+    pub fn subscribe(&self) -> broadcast::Receiver<(String, Vec<u8>)> {
+        self.updates.subscribe()
+    }
+
+    /// Answer an OSCQuery HTTP request for `path` with optional `query`
+    /// (`HOST_INFO` or an attribute name). Returns None for unknown paths.
+    pub fn http_response(&self, path: &str, query: Option<&str>) -> Option<Value> {
+        if query == Some("HOST_INFO") {
+            return Some(self.host_info.clone());
+        }
+
+        let path = if path.len() > 1 { path.trim_end_matches('/') } else { path };
+        let nodes = self.nodes.lock().ok()?;
+        let node = build_node(&nodes, path)?;
+
+        match query {
+            None | Some("") => Some(node),
+            Some(attr) => {
+                let value = node.get(attr)?.clone();
+                Some(json!({ attr: value }))
+            }
+        }
+    }
+}
+
+/// Methods accepted under the receive command prefix, with their type tags.
+const COMMAND_METHODS: &[(&str, &str)] = &[
+    ("/line", "s"),
+    ("/key/tap", "s"),
+    ("/key/down", "s"),
+    ("/key/up", "s"),
+    ("/key/type", "s"),
+    ("/mouse/move", "ii"),
+    ("/mouse/move_rel", "ii"),
+    ("/mouse/click", "s"),
+    ("/mouse/down", "s"),
+    ("/mouse/up", "s"),
+    ("/mouse/scroll", "ii"),
+    ("/midi/note_on", "iii"),
+    ("/midi/note_off", "iii"),
+    ("/midi/cc", "iii"),
+    ("/midi/raw", "iii"),
+];
+
+/// Build the OSCQuery JSON node for `path`, including all descendants.
+fn build_node(nodes: &BTreeMap<String, Node>, path: &str) -> Option<Value> {
+    let prefix = if path == "/" { "/".to_string() } else { format!("{}/", path) };
+    let leaf = nodes.get(path);
+    let mut has_children = false;
+
+    // Collect direct children names; deeper levels are built recursively
+    let mut children: BTreeMap<String, Value> = BTreeMap::new();
+    for address in nodes.keys().filter(|a| a.starts_with(&prefix)) {
+        has_children = true;
+        let name = address[prefix.len()..].split('/').next().unwrap_or("");
+        if name.is_empty() || children.contains_key(name) {
+            continue;
+        }
+        let child_path = format!("{}{}", prefix, name);
+        if let Some(child) = build_node(nodes, &child_path) {
+            children.insert(name.to_string(), child);
+        }
+    }
+
+    if leaf.is_none() && !has_children && path != "/" {
+        return None;
+    }
+
+    let mut obj = Map::new();
+    obj.insert("FULL_PATH".into(), json!(path));
+    match leaf {
+        Some(node) => {
+            obj.insert("ACCESS".into(), json!(node.access));
+            if let Some(types) = &node.types {
+                obj.insert("TYPE".into(), json!(types));
+            }
+            if !node.value.is_empty() {
+                obj.insert("VALUE".into(), Value::Array(node.value.iter().map(json_value).collect()));
+            }
+        }
+        None => {
+            obj.insert("ACCESS".into(), json!(0));
+        }
+    }
+    if !children.is_empty() {
+        obj.insert("CONTENTS".into(), Value::Object(children.into_iter().collect()));
+    }
+    Some(Value::Object(obj))
+}
+
+/// The OSC type tag character for an argument.
+fn type_tag(arg: &OscType) -> char {
+    match arg {
+        OscType::Int(_) => 'i',
+        OscType::Float(_) => 'f',
+        OscType::String(_) => 's',
+        OscType::Blob(_) => 'b',
+        OscType::Time(_) => 't',
+        OscType::Long(_) => 'h',
+        OscType::Double(_) => 'd',
+        OscType::Char(_) => 'c',
+        OscType::Color(_) => 'r',
+        OscType::Midi(_) => 'm',
+        OscType::Bool(true) => 'T',
+        OscType::Bool(false) => 'F',
+        OscType::Array(_) => '[',
+        OscType::Nil => 'N',
+        OscType::Inf => 'I',
+    }
+}
+
+/// OSCQuery JSON representation of an argument value.
+fn json_value(arg: &OscType) -> Value {
+    use base64::Engine;
+    match arg {
+        OscType::Int(i) => json!(i),
+        OscType::Float(f) => json!(f),
+        OscType::String(s) => json!(s),
+        OscType::Blob(b) => json!(base64::engine::general_purpose::STANDARD.encode(b)),
+        OscType::Time(t) => json!([t.seconds, t.fractional]),
+        OscType::Long(l) => json!(l),
+        OscType::Double(d) => json!(d),
+        OscType::Char(c) => json!(c.to_string()),
+        OscType::Color(c) => json!(format!("#{:02x}{:02x}{:02x}{:02x}", c.red, c.green, c.blue, c.alpha)),
+        OscType::Midi(m) => json!([m.port, m.status, m.data1, m.data2]),
+        OscType::Bool(b) => json!(b),
+        OscType::Array(a) => Value::Array(a.content.iter().map(json_value).collect()),
+        OscType::Nil | OscType::Inf => Value::Null,
+    }
+}
@@ -0,0 +1,108 @@
+//! Simulated bridges for `--dry-run` and `simulate = true`.
+//!
+//! They stand in for the keyboard, mouse and MIDI bridges and record what
+//! would have been done in an `ActionLog` instead of touching enigo or
+//! midir, so mio runs on machines without a desktop session or MIDI ports
+//! and tests can check what a line turned into. In the TUI and headless
+//! output, the `(simulated)` dispatch result of each line is the record.
+
+use super::{bridge_name, Bridge};
+use crate::protocol::{Command, Source};
+use crate::routing;
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// One command a simulated bridge took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Action {
+    /// The bridge simulated: `keyboard`, `mouse` or `midi`.
+    pub bridge: &'static str,
+    pub command: Command,
+    pub source: Source,
+}
+
+/// Actions kept by an `ActionLog`; older ones are dropped.
+pub const MAX_ACTIONS: usize = 1000;
+
+/// The latest actions of all simulated bridges, in order, up to
+/// `MAX_ACTIONS`. Clones share the log.
+#[derive(Debug, Clone, Default)]
+pub struct ActionLog {
+    actions: Arc<Mutex<VecDeque<Action>>>,
+}
+
+impl ActionLog {
+    /// Everything recorded so far.
+    pub fn actions(&self) -> Vec<Action> {
+        self.actions.lock().map(|a| a.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    /// The recorded commands as protocol lines, e.g. `key:tap,space`.
+    pub fn lines(&self) -> Vec<String> {
+        self.actions().iter().map(|a| routing::command_line(&a.command)).collect()
+    }
+
+    /// Return everything recorded so far and start over.
+    pub fn take(&self) -> Vec<Action> {
+        self.actions.lock().map(|mut a| a.drain(..).collect()).unwrap_or_default()
+    }
+
+    fn push(&self, action: Action) {
+        if let Ok(mut actions) = self.actions.lock() {
+            if actions.len() >= MAX_ACTIONS {
+                actions.pop_front();
+            }
+            actions.push_back(action);
+        }
+    }
+}
+
+/// Takes the commands of one built-in bridge and records them.
+pub struct SimulatedBridge {
+    name: &'static str,
+    log: ActionLog,
+    count: usize,
+}
+
+impl SimulatedBridge {
+    pub fn keyboard(log: ActionLog) -> Self {
+        Self::new("keyboard", log)
+    }
+
+    pub fn mouse(log: ActionLog) -> Self {
+        Self::new("mouse", log)
+    }
+
+    pub fn midi(log: ActionLog) -> Self {
+        Self::new("midi", log)
+    }
+
+    fn new(name: &'static str, log: ActionLog) -> Self {
+        Self { name, log, count: 0 }
+    }
+}
+
+impl Bridge for SimulatedBridge {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn handles(&self, cmd: &Command) -> bool {
+        bridge_name(cmd).0 == self.name
+    }
+
+    fn dispatch(&mut self, cmd: &Command, source: Source) -> Result<String> {
+        self.log.push(Action {
+            bridge: self.name,
+            command: cmd.clone(),
+            source,
+        });
+        self.count += 1;
+        Ok(format!("{} {} (simulated)", bridge_name(cmd).1, routing::command_line(cmd)))
+    }
+
+    fn status(&self) -> String {
+        format!("simulated, {} actions", self.count)
+    }
+}
@@ -3,79 +3,457 @@
 //! Runs as an async task on the tokio runtime. Broadcasts messages
 //! from the serial protocol to all connected WS clients.
 //! Also forwards incoming WS messages back to the app event loop.
+//!
+//! Access is controlled by [`super::ws_auth`]; with `tls_cert` and `tls_key`
+//! set every connection is TLS (wss:// and https://).
+//!
+//! Plain HTTP requests on the same port are answered with the HTTP API
+//! (see [`super::ws_api`]), the built-in dashboard, files from `static_dir`
+//! (see [`super::ws_static`]), or the OSCQuery namespace when it is enabled.
+//!
+//! `ws:<id>,<value>` lines are broadcast as one JSON object per message:
+//!
+//! ```json
+//! {"id":"pot","value":512,"ts":1718031234567,"source":"serial"}
+//! ```
+//!
+//! - `id`: the broadcast id, always a string.
+//! - `value`: `true`/`false`, `null`, a number, a string, or an array when the
+//!   value has several comma-separated parts (`ws:pos,1,2,3` -> `[1,2,3]`).
+//!   A value that is itself valid JSON (`{...}`, `[...]`, `"a, b"`) is embedded as-is.
+//! - `ts`: Unix time in milliseconds when mio dispatched the message.
+//! - `source`: where the line came from: `"serial"`, `"ws"`, `"osc"` or `"http"`.
+//!
+//! `ws:raw,<payload>` sends the payload verbatim.
+//!
+//! Clients can limit which ids they receive, see [`super::ws_topics`].
+//!
+//! The last message for every id is cached. New clients get the cached
+//! values for their topics right after the handshake, and any client can ask
+//! for them again with `{"get":"pot"}` (or a list, `*` wildcards allowed).
+//!
+//! Clients that ask for the `mio.msgpack` or `mio.cbor` subprotocol get the
+//! same messages as binary frames, and may send binary frames back, see
+//! [`super::ws_codec`].
 
-use anyhow::Result;
+use super::oscquery::OscNamespace;
+use super::ws_api::{self, Endpoint};
+use super::ws_codec::WsEncoding;
+use super::ws_auth::{self, Access, Permissions};
+use super::ws_static;
+use super::ws_stream::{self, Connection, Rewind};
+use super::ws_topics::TopicFilter;
+use super::Bridge;
+use crate::config::{WebSocketConfig, WsLagPolicy};
+use crate::protocol::{Command, Source};
+use anyhow::{anyhow, Result};
 use futures_util::{SinkExt, StreamExt};
+use std::collections::{BTreeMap, HashSet};
 use std::net::SocketAddr;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpListener;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, oneshot};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
 use tokio_tungstenite::tungstenite::Message;
 
-/// Shared client count, readable from the TUI.
-pub type ClientCount = Arc<AtomicUsize>;
+/// A snapshot of one connected client, shown in the TUI.
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub peer: SocketAddr,
+    /// Name of the `[[websocket.clients]]` entry the client authenticated as.
+    pub name: Option<String>,
+    pub connected_at: Instant,
+    pub messages_in: u64,
+    pub messages_out: u64,
+    /// Broadcasts dropped because the client lagged.
+    pub dropped: u64,
+    pub topics: String,
+    pub encoding: WsEncoding,
+}
+
+/// Live state of one connection, updated by its task.
+#[derive(Debug)]
+struct ClientEntry {
+    peer: SocketAddr,
+    name: Option<String>,
+    connected_at: Instant,
+    messages_in: AtomicU64,
+    messages_out: AtomicU64,
+    dropped: AtomicU64,
+    topics: Arc<Mutex<TopicFilter>>,
+    encoding: WsEncoding,
+    kick: tokio::sync::Notify,
+}
+
+/// All connected (and authenticated) clients, shared with the TUI.
+#[derive(Debug, Default)]
+pub struct ClientRegistry {
+    clients: Mutex<BTreeMap<u64, Arc<ClientEntry>>>,
+    next_id: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl ClientRegistry {
+    pub fn count(&self) -> usize {
+        self.clients.lock().map(|c| c.len()).unwrap_or(0)
+    }
+
+    /// Broadcasts dropped for lagging clients since startup.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// All connected clients, oldest first.
+    pub fn snapshot(&self) -> Vec<ClientInfo> {
+        let Ok(clients) = self.clients.lock() else {
+            return Vec::new();
+        };
+        clients
+            .iter()
+            .map(|(&id, entry)| ClientInfo {
+                id,
+                peer: entry.peer,
+                name: entry.name.clone(),
+                connected_at: entry.connected_at,
+                messages_in: entry.messages_in.load(Ordering::Relaxed),
+                messages_out: entry.messages_out.load(Ordering::Relaxed),
+                dropped: entry.dropped.load(Ordering::Relaxed),
+                topics: entry.topics.lock().map(|t| t.describe()).unwrap_or_default(),
+                encoding: entry.encoding,
+            })
+            .collect()
+    }
+
+    /// Disconnect a client. Returns false if it is already gone.
+    pub fn kick(&self, id: u64) -> bool {
+        match self.clients.lock().ok().and_then(|c| c.get(&id).cloned()) {
+            Some(entry) => {
+                entry.kick.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn register(
+        self: &Arc<Self>,
+        peer: SocketAddr,
+        name: Option<String>,
+        topics: Arc<Mutex<TopicFilter>>,
+        encoding: WsEncoding,
+    ) -> RegisteredClient {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let entry = Arc::new(ClientEntry {
+            peer,
+            name,
+            connected_at: Instant::now(),
+            messages_in: AtomicU64::new(0),
+            messages_out: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            topics,
+            encoding,
+            kick: tokio::sync::Notify::new(),
+        });
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.insert(id, entry.clone());
+        }
+        RegisteredClient {
+            registry: self.clone(),
+            id,
+            entry,
+        }
+    }
+}
+
+/// A connection's registry entry; removed when dropped.
+struct RegisteredClient {
+    registry: Arc<ClientRegistry>,
+    id: u64,
+    entry: Arc<ClientEntry>,
+}
+
+impl RegisteredClient {
+    fn count_dropped(&self, missed: u64) {
+        self.entry.dropped.fetch_add(missed, Ordering::Relaxed);
+        self.registry.dropped.fetch_add(missed, Ordering::Relaxed);
+    }
+}
+
+impl Drop for RegisteredClient {
+    fn drop(&mut self) {
+        if let Ok(mut clients) = self.registry.clients.lock() {
+            clients.remove(&self.id);
+        }
+    }
+}
+
+/// Largest HTTP request head we accept before giving up on a connection.
+const MAX_REQUEST_HEAD: usize = 8192;
+
+/// How long a client that did not authenticate in the URL has to send `{"auth":...}`.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Topic of `ws:raw` payloads for subscription filtering.
+pub const RAW_TOPIC: &str = "raw";
+
+/// A protocol line from a WebSocket or HTTP client, for the app to dispatch.
+#[derive(Debug)]
+pub struct WsInbound {
+    pub line: String,
+    pub source: Source,
+    /// Where to send the dispatch result, or None if the line did not parse.
+    /// Only set for `POST /command`, which waits for it.
+    pub reply: Option<oneshot::Sender<Option<String>>>,
+}
+
+impl WsInbound {
+    /// Hand the dispatch result back to whoever sent the line.
+    pub fn respond(self, result: Option<String>) {
+        if let Some(reply) = self.reply {
+            let _ = reply.send(result);
+        }
+    }
+}
+
+/// A message for all WebSocket clients subscribed to its topic.
+#[derive(Debug, Clone)]
+pub struct WsMessage {
+    pub topic: String,
+    pub text: String,
+    /// The message as a value, for binary encodings. None for raw payloads.
+    pub value: Option<Arc<serde_json::Value>>,
+}
+
+impl WsMessage {
+    /// A `ws:<id>,<value>` broadcast, with the id as topic.
+    pub fn broadcast(id: &str, value: &str, source: Source) -> Self {
+        let value = broadcast_value(id, value, source);
+        Self {
+            topic: id.to_string(),
+            text: value.to_string(),
+            value: Some(Arc::new(value)),
+        }
+    }
+
+    /// A `ws:raw` payload, sent verbatim.
+    pub fn raw(payload: &str) -> Self {
+        Self {
+            topic: RAW_TOPIC.to_string(),
+            text: payload.to_string(),
+            value: None,
+        }
+    }
+
+    /// The frame to send to a client using `encoding`. Raw payloads are
+    /// always sent as text.
+    pub fn to_frame(&self, encoding: WsEncoding) -> Message {
+        match &self.value {
+            Some(value) if encoding != WsEncoding::Json => encoding.encode(value),
+            _ => Message::text(self.text.clone()),
+        }
+    }
+}
+
+/// The last broadcast for each id, shared between the router and the server.
+#[derive(Debug, Clone, Default)]
+pub struct LastValueCache {
+    values: Arc<Mutex<BTreeMap<String, WsMessage>>>,
+}
+
+impl LastValueCache {
+    /// Remember `msg` as the latest value for its topic. Raw payloads have
+    /// no id and are not cached.
+    pub fn insert(&self, msg: &WsMessage) {
+        if msg.topic == RAW_TOPIC {
+            return;
+        }
+        if let Ok(mut values) = self.values.lock() {
+            values.insert(msg.topic.clone(), msg.clone());
+        }
+    }
+
+    /// All cached messages whose topic passes `filter`, ordered by id.
+    pub fn snapshot(&self, filter: &TopicFilter) -> Vec<WsMessage> {
+        self.values
+            .lock()
+            .map(|values| values.values().filter(|m| filter.matches(&m.topic)).cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// The WebSocket output: `ws:` commands go to every client of the server
+/// subscribed to `tx`.
+pub struct WsBridge {
+    pub tx: broadcast::Sender<WsMessage>,
+    /// Last value per broadcast id, replayed to new WebSocket clients.
+    pub cache: LastValueCache,
+}
+
+impl WsBridge {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+        Self {
+            tx,
+            cache: LastValueCache::default(),
+        }
+    }
+}
+
+impl Bridge for WsBridge {
+    fn name(&self) -> &str {
+        "websocket"
+    }
+
+    fn handles(&self, cmd: &Command) -> bool {
+        matches!(cmd, Command::WsBroadcast { .. } | Command::WsRaw(_))
+    }
+
+    fn dispatch(&mut self, cmd: &Command, source: Source) -> Result<String> {
+        let count = self.tx.receiver_count();
+        match cmd {
+            Command::WsBroadcast { id, value } => {
+                let msg = WsMessage::broadcast(id, value, source);
+                self.cache.insert(&msg);
+                let _ = self.tx.send(msg);
+                Ok(format!("WS broadcast {}={} ({} clients)", id, value, count))
+            }
+            Command::WsRaw(payload) => {
+                let _ = self.tx.send(WsMessage::raw(payload));
+                Ok(format!("WS raw ({} clients)", count))
+            }
+            _ => Err(anyhow!("WS ERROR: not a WebSocket command")),
+        }
+    }
+
+    fn status(&self) -> String {
+        format!("{} clients", self.tx.receiver_count())
+    }
+}
+
+/// Build the JSON object for a `ws:<id>,<value>` broadcast.
+fn broadcast_value(id: &str, value: &str, source: Source) -> serde_json::Value {
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    serde_json::json!({
+        "id": id,
+        "value": infer_value(value),
+        "ts": ts,
+        "source": source.as_str(),
+    })
+}
+
+/// Turn a protocol value into a typed JSON value.
+pub fn infer_value(value: &str) -> serde_json::Value {
+    let trimmed = value.trim();
+    if trimmed.starts_with(['{', '[', '"']) {
+        if let Ok(json) = serde_json::from_str(trimmed) {
+            return json;
+        }
+    }
+    if trimmed.contains(',') {
+        return serde_json::Value::Array(trimmed.split(',').map(infer_scalar).collect());
+    }
+    infer_scalar(trimmed)
+}
+
+fn infer_scalar(part: &str) -> serde_json::Value {
+    let part = part.trim();
+    match part {
+        "true" => return serde_json::Value::Bool(true),
+        "false" => return serde_json::Value::Bool(false),
+        "null" => return serde_json::Value::Null,
+        _ => {}
+    }
+    if let Ok(i) = part.parse::<i64>() {
+        return i.into();
+    }
+    match part.parse::<f64>() {
+        Ok(f) if f.is_finite() => f.into(),
+        _ => part.into(),
+    }
+}
 
 /// Start the WebSocket server as a tokio task.
-/// Returns the client count and the join handle.
+/// Returns the client registry and the join handle.
 pub async fn start_server(
-    host: &str,
-    port: u16,
-    broadcast_rx: broadcast::Sender<String>,
-    incoming_tx: tokio::sync::mpsc::Sender<String>,
-) -> Result<(ClientCount, tokio::task::JoinHandle<()>)> {
-    let addr: SocketAddr = format!("{}:{}", host, port).parse()?;
+    config: &WebSocketConfig,
+    broadcast_tx: broadcast::Sender<WsMessage>,
+    incoming_tx: tokio::sync::mpsc::Sender<WsInbound>,
+    cache: LastValueCache,
+    oscquery: Option<OscNamespace>,
+) -> Result<(Arc<ClientRegistry>, tokio::task::JoinHandle<()>)> {
+    let addr: SocketAddr = format!("{}:{}", config.host, config.port).parse()?;
+    let tls = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Some(ws_stream::tls_acceptor(Path::new(cert), Path::new(key))?),
+        (None, None) => None,
+        _ => anyhow::bail!("websocket.tls_cert and websocket.tls_key must be set together"),
+    };
     let listener = TcpListener::bind(&addr).await?;
-    let client_count = Arc::new(AtomicUsize::new(0));
-    let count_clone = client_count.clone();
+    let clients = Arc::new(ClientRegistry::default());
+    let shared = Shared {
+        clients: clients.clone(),
+        broadcast_tx,
+        incoming_tx,
+        cache,
+        oscquery,
+        static_dir: config.static_dir.as_ref().map(PathBuf::from),
+        dashboard: config.dashboard,
+        access: Arc::new(Access::new(config)),
+        lag_policy: config.lag_policy,
+        ping_interval: (config.ping_interval_ms > 0).then(|| Duration::from_millis(config.ping_interval_ms)),
+        client_timeout: Duration::from_millis(config.client_timeout_ms.max(1)),
+    };
 
-    eprintln!("[mio] WebSocket server listening on {}", addr);
+    let scheme = if tls.is_some() { "wss" } else { "ws" };
+    eprintln!("[mio] WebSocket server listening on {}://{}", scheme, addr);
 
     let handle = tokio::spawn(async move {
         loop {
             match listener.accept().await {
                 Ok((stream, peer)) => {
-                    let count = count_clone.clone();
-                    let mut rx = broadcast_rx.subscribe();
-                    let tx = incoming_tx.clone();
+                    let shared = shared.clone();
+                    let tls = tls.clone();
 
                     tokio::spawn(async move {
-                        count.fetch_add(1, Ordering::Relaxed);
-
-                        let ws_stream = match tokio_tungstenite::accept_async(stream).await {
-                            Ok(ws) => ws,
-                            Err(e) => {
-                                eprintln!("[mio] WS handshake failed for {}: {}", peer, e);
-                                count.fetch_sub(1, Ordering::Relaxed);
-                                return;
-                            }
+                        let conn: Box<dyn Connection> = match tls {
+                            Some(acceptor) => match acceptor.accept(stream).await {
+                                Ok(tls_stream) => Box::new(tls_stream),
+                                Err(_) => return,
+                            },
+                            None => Box::new(stream),
                         };
-
-                        let (mut ws_sink, mut ws_source) = ws_stream.split();
-
-                        // Task: forward broadcast messages to this client
-                        let sink_task = tokio::spawn(async move {
-                            while let Ok(msg) = rx.recv().await {
-                                if ws_sink.send(Message::text(msg)).await.is_err() {
-                                    break;
-                                }
-                            }
-                        });
-
-                        // Read incoming messages from the client
-                        while let Some(msg) = ws_source.next().await {
-                            match msg {
-                                Ok(Message::Text(text)) => {
-                                    let _ = tx.send(text.to_string()).await;
-                                }
-                                Ok(Message::Close(_)) => break,
-                                Err(_) => break,
-                                _ => {}
-                            }
+                        let Ok((request, mut stream)) = read_request_head(conn).await else {
+                            return;
+                        };
+                        if !request.is_websocket_upgrade() {
+                            let _ = serve_http(stream, &request, &shared).await;
+                            return;
                         }
-
-                        sink_task.abort();
-                        count.fetch_sub(1, Ordering::Relaxed);
+                        if !shared.access.origin_allowed(request.header("origin"), request.header("host")) {
+                            let _ = write_response(&mut stream, &HttpResponse::error("403 Forbidden"), false).await;
+                            return;
+                        }
+                        // A token in the URL must be valid; without one the
+                        // client may still authenticate with its first message
+                        let token = ws_auth::request_token(request.query.as_deref(), request.header("authorization"));
+                        let permissions = shared.access.authenticate(token.as_deref());
+                        if token.is_some() && permissions.is_none() {
+                            let _ = write_response(&mut stream, &HttpResponse::error("401 Unauthorized"), false).await;
+                            return;
+                        }
+                        let topics = TopicFilter::from_request(&request.path, request.query.as_deref());
+                        let encoding = request.header("sec-websocket-protocol").and_then(WsEncoding::negotiate);
+                        handle_ws_client(stream, peer, shared, topics, permissions, encoding).await;
                     });
                 }
                 Err(e) => {
@@ -85,5 +463,646 @@ pub async fn start_server(
         }
     });
 
-    Ok((client_count, handle))
+    Ok((clients, handle))
+}
+
+/// Server state every connection task gets a clone of.
+#[derive(Clone)]
+struct Shared {
+    clients: Arc<ClientRegistry>,
+    broadcast_tx: broadcast::Sender<WsMessage>,
+    incoming_tx: tokio::sync::mpsc::Sender<WsInbound>,
+    cache: LastValueCache,
+    oscquery: Option<OscNamespace>,
+    static_dir: Option<PathBuf>,
+    dashboard: bool,
+    access: Arc<Access>,
+    lag_policy: WsLagPolicy,
+    ping_interval: Option<Duration>,
+    client_timeout: Duration,
+}
+
+/// Run one WebSocket connection until it closes.
+/// `permissions` is None when the client still has to authenticate.
+/// `encoding` is the negotiated subprotocol, if the client asked for one of ours.
+async fn handle_ws_client(
+    stream: Rewind,
+    peer: SocketAddr,
+    shared: Shared,
+    topics: TopicFilter,
+    permissions: Option<Permissions>,
+    encoding: Option<WsEncoding>,
+) {
+    let Shared {
+        clients,
+        broadcast_tx,
+        incoming_tx: tx,
+        cache,
+        oscquery,
+        access,
+        lag_policy,
+        ping_interval,
+        client_timeout,
+        ..
+    } = shared;
+
+    let ws_stream = match tokio_tungstenite::accept_hdr_async(stream, AcceptSubprotocol(encoding)).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            eprintln!("[mio] WS handshake failed for {}: {}", peer, e);
+            return;
+        }
+    };
+
+    let (mut ws_sink, mut ws_source) = ws_stream.split();
+    let encoding = encoding.unwrap_or_default();
+
+    let permissions = match permissions {
+        Some(permissions) => permissions,
+        None => {
+            let first = tokio::time::timeout(AUTH_TIMEOUT, ws_source.next()).await;
+            let token = match first {
+                Ok(Some(Ok(msg))) => encoding.decode(&msg).and_then(|text| ws_auth::auth_message(&text)),
+                _ => None,
+            };
+            match token.and_then(|t| access.authenticate(Some(&t))) {
+                Some(permissions) => permissions,
+                None => {
+                    let _ = ws_sink.send(close_message(CloseCode::Policy, "unauthorized")).await;
+                    return;
+                }
+            }
+        }
+    };
+
+    // Replay the last known values. Subscribe before taking the snapshot,
+    // so nothing sent in between is lost.
+    let mut rx = broadcast_tx.subscribe();
+    for msg in cache.snapshot(&topics) {
+        if ws_sink.send(msg.to_frame(encoding)).await.is_err() {
+            return;
+        }
+    }
+
+    // Replies to this client only ({"get":...} results, errors), sent by the sink task
+    let (reply_tx, mut reply_rx) = tokio::sync::mpsc::unbounded_channel::<Message>();
+
+    // OSCQuery LISTEN subscriptions for this client
+    let listening: Arc<Mutex<HashSet<String>>> = Arc::default();
+    let mut osc_updates = oscquery.as_ref().map(|ns| ns.subscribe());
+    let sink_listening = listening.clone();
+
+    // Broadcast topics this client is subscribed to
+    let topics = Arc::new(Mutex::new(topics));
+    let sink_topics = topics.clone();
+
+    let client = Arc::new(clients.register(peer, permissions.name.clone(), topics.clone(), encoding));
+    let sink_client = client.clone();
+
+    // When the client last sent anything (including pongs)
+    let last_seen = Arc::new(Mutex::new(Instant::now()));
+    let sink_last_seen = last_seen.clone();
+
+    // Task: forward broadcast messages (and listened OSC values) to this client
+    let mut sink_task = tokio::spawn(async move {
+        let mut heartbeat = ping_interval.map(|every| {
+            let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            interval
+        });
+
+        loop {
+            let msg = tokio::select! {
+                msg = rx.recv() => match msg {
+                    Ok(msg) => {
+                        let wanted = sink_topics.lock().map(|t| t.matches(&msg.topic)).unwrap_or(true);
+                        if !wanted {
+                            continue;
+                        }
+                        msg.to_frame(encoding)
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        sink_client.count_dropped(missed);
+                        match lag_policy {
+                            WsLagPolicy::Skip => {
+                                let dropped = sink_client.entry.dropped.load(Ordering::Relaxed);
+                                encoding.encode(&serde_json::json!({ "lagged": missed, "dropped": dropped }))
+                            }
+                            WsLagPolicy::Disconnect => close_message(CloseCode::Again, "too slow"),
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                reply = reply_rx.recv() => match reply {
+                    Some(msg) => msg,
+                    None => break,
+                },
+                update = next_osc_update(&mut osc_updates) => match update {
+                    Some((address, packet)) => {
+                        let wanted = sink_listening.lock().map(|l| l.contains(&address)).unwrap_or(false);
+                        if !wanted {
+                            continue;
+                        }
+                        Message::binary(packet)
+                    }
+                    None => {
+                        osc_updates = None;
+                        continue;
+                    }
+                },
+                _ = next_tick(&mut heartbeat) => {
+                    let silent = sink_last_seen.lock().map(|t| t.elapsed() > client_timeout).unwrap_or(false);
+                    if silent {
+                        close_message(CloseCode::Away, "timeout")
+                    } else {
+                        Message::Ping(Default::default())
+                    }
+                }
+            };
+
+            // A client that stops reading must not block this task forever
+            let closing = matches!(msg, Message::Close(_));
+            let counted = matches!(msg, Message::Text(_) | Message::Binary(_));
+            match tokio::time::timeout(client_timeout, ws_sink.send(msg)).await {
+                Ok(Ok(())) if !closing => {
+                    if counted {
+                        sink_client.entry.messages_out.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                _ => break,
+            }
+        }
+    });
+
+    // Read incoming messages from the client until it leaves or the sink gives up on it
+    loop {
+        let msg = tokio::select! {
+            msg = ws_source.next() => msg,
+            _ = &mut sink_task => break,
+            _ = client.entry.kick.notified() => {
+                // The sink sends the close frame and then ends, which ends this loop
+                let _ = reply_tx.send(close_message(CloseCode::Policy, "kicked"));
+                continue;
+            }
+        };
+        let Some(msg) = msg else {
+            break;
+        };
+        if let Ok(mut seen) = last_seen.lock() {
+            *seen = Instant::now();
+        }
+        if matches!(msg, Ok(Message::Text(_) | Message::Binary(_))) {
+            client.entry.messages_in.fetch_add(1, Ordering::Relaxed);
+        }
+        match msg {
+            Ok(msg @ (Message::Text(_) | Message::Binary(_))) => {
+                let Some(text) = encoding.decode(&msg) else {
+                    continue;
+                };
+                if oscquery.is_some() && handle_oscquery_command(&text, &listening) {
+                    continue;
+                }
+                if let Ok(mut topics) = topics.lock() {
+                    if topics.apply_control(&text) {
+                        continue;
+                    }
+                }
+                if let Some(filter) = get_request(&text) {
+                    for msg in cache.snapshot(&filter) {
+                        let _ = reply_tx.send(msg.to_frame(encoding));
+                    }
+                    continue;
+                }
+                if !permissions.allows(&text) {
+                    let error = serde_json::json!({ "error": "command not allowed", "line": text });
+                    let _ = reply_tx.send(encoding.encode(&error));
+                    continue;
+                }
+                let inbound = WsInbound {
+                    line: text,
+                    source: Source::WebSocket,
+                    reply: None,
+                };
+                let _ = tx.send(inbound).await;
+            }
+            Ok(Message::Close(_)) => break,
+            Err(_) => break,
+            _ => {}
+        }
+    }
+
+    sink_task.abort();
+}
+
+/// Handshake callback that confirms the negotiated subprotocol, if any.
+struct AcceptSubprotocol(Option<WsEncoding>);
+
+impl Callback for AcceptSubprotocol {
+    fn on_request(self, _request: &Request, mut response: Response) -> Result<Response, ErrorResponse> {
+        if let Some(encoding) = self.0 {
+            response
+                .headers_mut()
+                .insert("sec-websocket-protocol", HeaderValue::from_static(encoding.subprotocol()));
+        }
+        Ok(response)
+    }
+}
+
+fn close_message(code: CloseCode, reason: &str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
+
+/// Wait for the next heartbeat tick, or forever if the heartbeat is off.
+async fn next_tick(heartbeat: &mut Option<tokio::time::Interval>) {
+    match heartbeat {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Parse a `{"get":"id"}` / `{"get":["a","b*"]}` request into the ids it asks for.
+fn get_request(text: &str) -> Option<TopicFilter> {
+    if !text.trim_start().starts_with('{') {
+        return None;
+    }
+    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+    let ids: Vec<String> = match value.get("get")? {
+        serde_json::Value::String(id) => vec![id.clone()],
+        serde_json::Value::Array(ids) => ids.iter().filter_map(|v| v.as_str()).map(String::from).collect(),
+        _ => return None,
+    };
+    let mut filter = TopicFilter::default();
+    filter.subscribe(ids);
+    Some(filter)
+}
+
+/// Wait for the next OSCQuery value update, or forever if there is no namespace.
+/// Returns None once the update channel has closed.
+async fn next_osc_update(
+    updates: &mut Option<broadcast::Receiver<(String, Vec<u8>)>>,
+) -> Option<(String, Vec<u8>)> {
+    let Some(rx) = updates else {
+        return std::future::pending().await;
+    };
+    loop {
+        match rx.recv().await {
+            Ok(update) => return Some(update),
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Handle an OSCQuery `{"COMMAND":"LISTEN"|"IGNORE","DATA":"/addr"}` message.
+/// Returns false if the text is not such a command. Only reached once the
+/// client has authenticated, like the OSCQuery HTTP reads.
+fn handle_oscquery_command(text: &str, listening: &Mutex<HashSet<String>>) -> bool {
+    if !text.trim_start().starts_with('{') {
+        return false;
+    }
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(text) else {
+        return false;
+    };
+    let (Some(command), Some(address)) = (value["COMMAND"].as_str(), value["DATA"].as_str()) else {
+        return false;
+    };
+    let Ok(mut listening) = listening.lock() else {
+        return true;
+    };
+    match command {
+        "LISTEN" => {
+            listening.insert(address.to_string());
+            true
+        }
+        "IGNORE" => {
+            listening.remove(address);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The request line and headers of an HTTP request.
+struct RequestHead {
+    method: String,
+    path: String,
+    query: Option<String>,
+    headers: Vec<(String, String)>,
+    len: usize,
+}
+
+impl RequestHead {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn is_websocket_upgrade(&self) -> bool {
+        self.header("upgrade").is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+    }
+}
+
+/// Read the request head. The returned stream replays everything read so
+/// far, so a WebSocket handshake can still be performed on it.
+async fn read_request_head(mut conn: Box<dyn Connection>) -> std::io::Result<(RequestHead, Rewind)> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+
+    loop {
+        let n = tokio::time::timeout_at(deadline, conn.read(&mut chunk))
+            .await
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "request head timeout"))??;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            let request = parse_request_head(&buf[..end + 4]).ok_or(std::io::ErrorKind::InvalidData)?;
+            return Ok((request, Rewind::new(buf, conn)));
+        }
+        if buf.len() >= MAX_REQUEST_HEAD {
+            return Err(std::io::ErrorKind::InvalidData.into());
+        }
+    }
+}
+
+fn parse_request_head(bytes: &[u8]) -> Option<RequestHead> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    let mut lines = text.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?;
+    let (path, query) = match target.split_once('?') {
+        Some((p, q)) => (p.to_string(), Some(q.to_string())),
+        None => (target.to_string(), None),
+    };
+    let headers = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect();
+    Some(RequestHead {
+        method,
+        path,
+        query,
+        headers,
+        len: bytes.len(),
+    })
+}
+
+/// A response to a plain HTTP request.
+struct HttpResponse {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+    /// `Access-Control-Allow-Origin`, for origins the config lets read responses.
+    allow_origin: Option<String>,
+}
+
+impl HttpResponse {
+    fn ok(content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: "200 OK",
+            content_type,
+            body: body.into(),
+            allow_origin: None,
+        }
+    }
+
+    fn json(status: &'static str, value: &serde_json::Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+            allow_origin: None,
+        }
+    }
+
+    fn error(status: &'static str) -> Self {
+        let body = status.split_once(' ').map_or(status, |(_, reason)| reason);
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.as_bytes().to_vec(),
+            allow_origin: None,
+        }
+    }
+
+    fn with_allow_origin(mut self, origin: Option<&str>) -> Self {
+        self.allow_origin = origin.map(String::from);
+        self
+    }
+}
+
+/// Answer a plain HTTP request with the API, the dashboard, a static file or OSCQuery JSON.
+async fn serve_http(mut stream: Rewind, request: &RequestHead, shared: &Shared) -> Result<()> {
+    // Consume the head we peeked at
+    let mut head = vec![0u8; request.len];
+    stream.read_exact(&mut head).await?;
+
+    if let Some(endpoint) = Endpoint::from_path(&request.path) {
+        return serve_api(stream, request, shared, endpoint).await;
+    }
+
+    let cors = shared.access.cors_origin(request.header("origin"));
+    let response = match request.method.as_str() {
+        // With a token set, the pages and OSCQuery take the same token as the API
+        "GET" | "HEAD" if shared.access.requires_token() => match authorize(request, shared) {
+            Ok(_) => route_get(request, shared).await.with_allow_origin(cors),
+            Err(response) => response,
+        },
+        "GET" | "HEAD" => route_get(request, shared).await.with_allow_origin(cors),
+        _ => HttpResponse::error("405 Method Not Allowed"),
+    };
+
+    write_response(&mut stream, &response, request.method == "HEAD").await
+}
+
+/// Answer an API request, after checking its origin and token.
+/// Responses to other allowed origins may be read by their pages, except
+/// `/command` ones.
+async fn serve_api(mut stream: Rewind, request: &RequestHead, shared: &Shared, endpoint: Endpoint) -> Result<()> {
+    let cors = shared.access.cors_origin(request.header("origin"));
+    let method = request.method.as_str();
+    let head_only = method == "HEAD" && endpoint.method() == "GET";
+    let response = if method != endpoint.method() && !head_only {
+        HttpResponse::error("405 Method Not Allowed")
+    } else {
+        match authorize(request, shared) {
+            Err(response) => response,
+            Ok(permissions) => {
+                let topics = TopicFilter::from_request("/", request.query.as_deref());
+                match endpoint {
+                    Endpoint::Events if !head_only => {
+                        // Subscribe before taking the snapshot, so nothing sent in between is lost
+                        let rx = shared.broadcast_tx.subscribe();
+                        let replay = shared.cache.snapshot(&topics);
+                        let _ = ws_api::stream_events(
+                            &mut stream,
+                            rx,
+                            &topics,
+                            replay,
+                            cors,
+                            shared.ping_interval,
+                            shared.client_timeout,
+                        )
+                        .await;
+                        return Ok(());
+                    }
+                    Endpoint::Events => HttpResponse::ok("text/event-stream", Vec::new()).with_allow_origin(cors),
+                    Endpoint::Values => {
+                        HttpResponse::ok("application/json", ws_api::values_json(&shared.cache, &topics).to_string())
+                            .with_allow_origin(cors)
+                    }
+                    Endpoint::Command => run_commands(&mut stream, request, shared, &permissions).await,
+                }
+            }
+        }
+    };
+    write_response(&mut stream, &response, head_only).await
+}
+
+/// Check a plain HTTP request's origin and token.
+/// Returns the client's permissions, or the error response to send.
+fn authorize(request: &RequestHead, shared: &Shared) -> Result<Permissions, HttpResponse> {
+    if !shared.access.origin_allowed(request.header("origin"), request.header("host")) {
+        return Err(HttpResponse::error("403 Forbidden"));
+    }
+    let token = ws_auth::request_token(request.query.as_deref(), request.header("authorization"));
+    shared
+        .access
+        .authenticate(token.as_deref())
+        .ok_or_else(|| HttpResponse::error("401 Unauthorized"))
+}
+
+/// Read a `POST /command` body and dispatch its lines through the app.
+async fn run_commands(
+    stream: &mut Rewind,
+    request: &RequestHead,
+    shared: &Shared,
+    permissions: &Permissions,
+) -> HttpResponse {
+    // A custom header makes browsers ask first (CORS preflight), which we never allow
+    if request.header(ws_api::COMMAND_HEADER).is_none() {
+        let error = serde_json::json!({ "error": format!("missing {} header", ws_api::COMMAND_HEADER) });
+        return HttpResponse::json("400 Bad Request", &error);
+    }
+    let Some(len) = request.header("content-length").and_then(|l| l.parse::<usize>().ok()) else {
+        return HttpResponse::error("411 Length Required");
+    };
+    if len > ws_api::MAX_COMMAND_BODY {
+        return HttpResponse::error("413 Payload Too Large");
+    }
+    let mut body = vec![0u8; len];
+    match tokio::time::timeout(shared.client_timeout, stream.read_exact(&mut body)).await {
+        Ok(Ok(_)) => {}
+        _ => return HttpResponse::error("400 Bad Request"),
+    }
+    let Ok(body) = String::from_utf8(body) else {
+        return HttpResponse::error("400 Bad Request");
+    };
+
+    let lines = ws_api::command_lines(&body);
+    if let Some(line) = lines.iter().find(|l| !permissions.allows(l)) {
+        let error = serde_json::json!({ "error": "command not allowed", "line": line });
+        return HttpResponse::json("403 Forbidden", &error);
+    }
+
+    let mut results = Vec::with_capacity(lines.len());
+    let mut all_ok = true;
+    for line in lines {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let inbound = WsInbound {
+            line: line.clone(),
+            source: Source::Http,
+            reply: Some(reply_tx),
+        };
+        if shared.incoming_tx.send(inbound).await.is_err() {
+            return HttpResponse::error("503 Service Unavailable");
+        }
+        match tokio::time::timeout(ws_api::COMMAND_TIMEOUT, reply_rx).await {
+            Ok(Ok(Some(result))) => results.push(serde_json::json!({ "line": line, "result": result })),
+            Ok(Ok(None)) => {
+                all_ok = false;
+                results.push(serde_json::json!({ "line": line, "error": "unrecognized command" }));
+            }
+            _ => return HttpResponse::error("504 Gateway Timeout"),
+        }
+    }
+    let status = if all_ok { "200 OK" } else { "400 Bad Request" };
+    HttpResponse::json(status, &serde_json::Value::Array(results))
+}
+
+/// Write a complete HTTP response and close the connection.
+async fn write_response(stream: &mut Rewind, response: &HttpResponse, head_only: bool) -> Result<()> {
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        ws_api::allow_origin_header(response.allow_origin.as_deref()),
+    );
+    stream.write_all(header.as_bytes()).await?;
+    if !head_only {
+        stream.write_all(&response.body).await?;
+    }
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Pick the response for a GET. Browsers asking for `/` get the page
+/// (`index.html` from `static_dir`, else the dashboard); other clients get
+/// the OSCQuery root, so both share the port.
+async fn route_get(request: &RequestHead, shared: &Shared) -> HttpResponse {
+    let path = request.path.as_str();
+    let wants_html = request.header("accept").is_some_and(|a| a.contains("text/html"));
+
+    if shared.dashboard && path.trim_end_matches('/') == ws_static::DASHBOARD_PATH {
+        return HttpResponse::ok("text/html; charset=utf-8", ws_static::DASHBOARD_HTML);
+    }
+
+    if path != "/" || wants_html {
+        if let Some(file) = shared.static_dir.as_deref().and_then(|root| ws_static::resolve(root, path)) {
+            return match tokio::fs::read(&file).await {
+                Ok(body) => HttpResponse::ok(ws_static::content_type(&file), body),
+                Err(_) => HttpResponse::error("500 Internal Server Error"),
+            };
+        }
+    }
+
+    if path == "/" && wants_html && shared.dashboard {
+        return HttpResponse::ok("text/html; charset=utf-8", ws_static::DASHBOARD_HTML);
+    }
+
+    let query = ws_auth::strip_token(request.query.as_deref());
+    match shared.oscquery.as_ref().and_then(|ns| ns.http_response(path, query.as_deref())) {
+        Some(json) => HttpResponse::ok("application/json", json.to_string()),
+        None => HttpResponse::error("404 Not Found"),
+    }
+}
+
+/// Decode `%XX` escapes in a URL component. Invalid escapes are kept as-is.
+pub(super) fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(b) = s.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
 }
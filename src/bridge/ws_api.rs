@@ -0,0 +1,155 @@
+//! HTTP API on the WebSocket port, for clients that can't speak WebSocket.
+//!
+//! - `GET /events`: Server-Sent Events, one `data:` event per broadcast
+//!   (the same JSON as over WebSocket), starting with the cached values.
+//!   `?topics=` filters like it does for WebSocket clients.
+//! - `GET /values`: the last value for every id, as a JSON object keyed by id.
+//! - `POST /command`: protocol lines in the body, one per line. Each is
+//!   dispatched like a line from a WebSocket client and the response lists
+//!   the result for every line.
+//!
+//! All three need the same token as WebSocket clients (`?token=` or
+//! `Authorization: Bearer`), and commands are checked against its permissions.
+//! `POST /command` also needs an `X-Mio-Command` header (any value), so a
+//! web page can't send commands without a CORS preflight, which mio never
+//! grants. Pages from `allowed_origins` may read `/events` and `/values`.
+
+use super::websocket::{LastValueCache, WsMessage};
+use super::ws_topics::TopicFilter;
+use std::time::Duration;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::broadcast;
+
+/// Largest `POST /command` body we accept.
+pub const MAX_COMMAND_BODY: usize = 64 * 1024;
+
+/// Header `POST /command` requests must carry.
+pub const COMMAND_HEADER: &str = "X-Mio-Command";
+
+/// How long `POST /command` waits for the app to dispatch its lines.
+pub const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The API endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    Events,
+    Values,
+    Command,
+}
+
+impl Endpoint {
+    /// The endpoint for a URL path, if it is one.
+    pub fn from_path(path: &str) -> Option<Self> {
+        match path.trim_end_matches('/') {
+            "/events" => Some(Endpoint::Events),
+            "/values" => Some(Endpoint::Values),
+            "/command" => Some(Endpoint::Command),
+            _ => None,
+        }
+    }
+
+    /// The HTTP method the endpoint answers (HEAD is also allowed for GET).
+    pub fn method(&self) -> &'static str {
+        match self {
+            Endpoint::Events | Endpoint::Values => "GET",
+            Endpoint::Command => "POST",
+        }
+    }
+}
+
+/// The cached values passing `filter`, as `{"<id>": {"id":...,"value":...}, ...}`.
+pub fn values_json(cache: &LastValueCache, filter: &TopicFilter) -> serde_json::Value {
+    let values = cache
+        .snapshot(filter)
+        .into_iter()
+        .map(|msg| {
+            let value = match &msg.value {
+                Some(value) => value.as_ref().clone(),
+                None => serde_json::Value::String(msg.text.clone()),
+            };
+            (msg.topic, value)
+        })
+        .collect();
+    serde_json::Value::Object(values)
+}
+
+/// Format one SSE event. Multi-line data becomes several `data:` fields.
+pub fn sse_event(event: Option<&str>, data: &str) -> String {
+    let mut out = String::new();
+    if let Some(event) = event {
+        out.push_str(&format!("event: {}\n", event));
+    }
+    for line in data.lines() {
+        out.push_str(&format!("data: {}\n", line));
+    }
+    if data.is_empty() {
+        out.push_str("data: \n");
+    }
+    out.push('\n');
+    out
+}
+
+/// The `Access-Control-Allow-Origin` header line for `origin`, or nothing.
+pub fn allow_origin_header(origin: Option<&str>) -> String {
+    origin.map_or_else(String::new, |origin| format!("Access-Control-Allow-Origin: {}\r\n", origin))
+}
+
+/// Protocol lines from a `POST /command` body, skipping blank lines.
+pub fn command_lines(body: &str) -> Vec<String> {
+    body.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Stream broadcasts to an SSE client until it disconnects or stops reading.
+/// `replay` is sent first; with `keepalive` set a comment is sent whenever
+/// nothing else was for that long. `allow_origin` is the CORS origin, if any.
+pub async fn stream_events<W: AsyncWrite + Unpin>(
+    out: &mut W,
+    mut rx: broadcast::Receiver<WsMessage>,
+    filter: &TopicFilter,
+    replay: Vec<WsMessage>,
+    allow_origin: Option<&str>,
+    keepalive: Option<Duration>,
+    write_timeout: Duration,
+) -> std::io::Result<()> {
+    let header = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n{}Connection: close\r\n\r\n",
+        allow_origin_header(allow_origin)
+    );
+    write_timed(out, &header, write_timeout).await?;
+    for msg in replay {
+        write_timed(out, &sse_event(None, &msg.text), write_timeout).await?;
+    }
+
+    loop {
+        let recv = async {
+            match keepalive {
+                Some(every) => tokio::time::timeout(every, rx.recv()).await.ok(),
+                None => Some(rx.recv().await),
+            }
+        };
+        let event = match recv.await {
+            Some(Ok(msg)) if filter.matches(&msg.topic) => sse_event(None, &msg.text),
+            Some(Ok(_)) => continue,
+            Some(Err(broadcast::error::RecvError::Lagged(missed))) => {
+                sse_event(Some("lagged"), &serde_json::json!({ "lagged": missed }).to_string())
+            }
+            Some(Err(broadcast::error::RecvError::Closed)) => return Ok(()),
+            None => ": keepalive\n\n".to_string(),
+        };
+        write_timed(out, &event, write_timeout).await?;
+    }
+}
+
+async fn write_timed<W: AsyncWrite + Unpin>(out: &mut W, text: &str, timeout: Duration) -> std::io::Result<()> {
+    let write = async {
+        out.write_all(text.as_bytes()).await?;
+        out.flush().await
+    };
+    tokio::time::timeout(timeout, write)
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "SSE client stopped reading"))?
+}
@@ -0,0 +1,149 @@
+//! Access control for the WebSocket server.
+//!
+//! With a `token` (or `[[websocket.clients]]`) configured, a client must
+//! present a token, either in the URL (`?token=...`), as an
+//! `Authorization: Bearer ...` header, or as its first message
+//! (`{"auth":"..."}`). Plain HTTP requests (the API, the dashboard, static
+//! files and OSCQuery) take the same token in the URL or header. The token
+//! decides which command prefixes the client may send inbound. Browsers are
+//! only let in from pages mio serves itself and from `allowed_origins`.
+
+use crate::config::WebSocketConfig;
+
+/// What one authenticated client may do.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Permissions {
+    /// Name of the matching `[[websocket.clients]]` entry, if any.
+    pub name: Option<String>,
+    /// Command prefixes the client may send (e.g. `ws:`, `key:tap`). Empty allows everything.
+    pub allowed_commands: Vec<String>,
+}
+
+impl Permissions {
+    /// Whether the client may send this protocol line.
+    pub fn allows(&self, line: &str) -> bool {
+        let line = line.trim();
+        self.allowed_commands.is_empty() || self.allowed_commands.iter().any(|p| line.starts_with(p.as_str()))
+    }
+}
+
+/// Tokens, origins and permissions from the `[websocket]` config.
+#[derive(Debug, Clone, Default)]
+pub struct Access {
+    token: Option<String>,
+    default: Permissions,
+    clients: Vec<(String, Permissions)>,
+    allowed_origins: Vec<String>,
+}
+
+impl Access {
+    pub fn new(config: &WebSocketConfig) -> Self {
+        let clients = config
+            .clients
+            .iter()
+            .map(|c| {
+                let permissions = Permissions {
+                    name: Some(c.name.clone()),
+                    allowed_commands: c.allowed_commands.clone(),
+                };
+                (c.token.clone(), permissions)
+            })
+            .collect();
+        Self {
+            token: config.token.clone().filter(|t| !t.is_empty()),
+            default: Permissions {
+                name: None,
+                allowed_commands: config.allowed_commands.clone(),
+            },
+            clients,
+            allowed_origins: config.allowed_origins.clone(),
+        }
+    }
+
+    /// Whether clients must present a token before anything else.
+    pub fn requires_token(&self) -> bool {
+        self.token.is_some() || !self.clients.is_empty()
+    }
+
+    /// Permissions for a presented token. Without required tokens every
+    /// client gets the default permissions. Returns None if the token is
+    /// required and missing or wrong.
+    pub fn authenticate(&self, token: Option<&str>) -> Option<Permissions> {
+        if !self.requires_token() {
+            return Some(self.default.clone());
+        }
+        let token = token?;
+        if let Some((_, permissions)) = self.clients.iter().find(|(t, _)| constant_time_eq(t, token)) {
+            return Some(permissions.clone());
+        }
+        match &self.token {
+            Some(t) if constant_time_eq(t, token) => Some(self.default.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether a request with this `Origin` header is accepted: pages served
+    /// from this server (`host` is the request's `Host` header) and origins
+    /// in `allowed_origins`. Requests without an Origin (non-browser clients)
+    /// always are.
+    pub fn origin_allowed(&self, origin: Option<&str>, host: Option<&str>) -> bool {
+        match origin {
+            None => true,
+            Some(origin) => host.is_some_and(|host| same_origin(origin, host)) || self.listed(origin),
+        }
+    }
+
+    /// The `Access-Control-Allow-Origin` value for a response: the request's
+    /// origin if `allowed_origins` lists it. Same-origin pages don't need one.
+    pub fn cors_origin<'a>(&self, origin: Option<&'a str>) -> Option<&'a str> {
+        origin.filter(|origin| self.listed(origin))
+    }
+
+    fn listed(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|o| o == "*" || o.trim_end_matches('/').eq_ignore_ascii_case(origin))
+    }
+}
+
+/// Whether `origin` (`scheme://host[:port]`) names the server at `host` (`host[:port]`).
+fn same_origin(origin: &str, host: &str) -> bool {
+    origin
+        .split_once("://")
+        .is_some_and(|(_, authority)| authority.trim_end_matches('/').eq_ignore_ascii_case(host))
+}
+
+/// The token from a `token=` query parameter or an `Authorization: Bearer` header.
+pub fn request_token(query: Option<&str>, authorization: Option<&str>) -> Option<String> {
+    let from_query = query
+        .unwrap_or("")
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+        .map(super::websocket::percent_decode);
+    from_query.or_else(|| {
+        // The auth scheme is case-insensitive (RFC 7235)
+        let (scheme, token) = authorization?.trim().split_once(' ')?;
+        scheme.eq_ignore_ascii_case("bearer").then(|| token.trim().to_string())
+    })
+}
+
+/// The query string without its `token=` parameter, None if nothing else is left.
+pub fn strip_token(query: Option<&str>) -> Option<String> {
+    let rest: Vec<&str> = query?.split('&').filter(|pair| !pair.starts_with("token=")).collect();
+    (!rest.is_empty()).then(|| rest.join("&"))
+}
+
+/// The token from a `{"auth":"..."}` message.
+pub fn auth_message(text: &str) -> Option<String> {
+    if !text.trim_start().starts_with('{') {
+        return None;
+    }
+    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+    value.get("auth")?.as_str().map(String::from)
+}
+
+/// Compare tokens without returning early on the first differing byte.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,86 @@
+//! Per-client message encoding for the WebSocket server.
+//!
+//! Clients pick an encoding with the WebSocket subprotocol: `mio.json`
+//! (the default, text frames), `mio.msgpack` or `mio.cbor` (binary frames).
+//! Binary clients may also send MessagePack/CBOR inbound: a string is a
+//! protocol line, a map is a control message such as `{"subscribe":[...]}`.
+
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::Message;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WsEncoding {
+    #[default]
+    Json,
+    MsgPack,
+    Cbor,
+}
+
+impl WsEncoding {
+    /// The subprotocol name for this encoding.
+    pub fn subprotocol(&self) -> &'static str {
+        match self {
+            WsEncoding::Json => "mio.json",
+            WsEncoding::MsgPack => "mio.msgpack",
+            WsEncoding::Cbor => "mio.cbor",
+        }
+    }
+
+    /// Short name for display.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WsEncoding::Json => "json",
+            WsEncoding::MsgPack => "msgpack",
+            WsEncoding::Cbor => "cbor",
+        }
+    }
+
+    /// Pick the first supported subprotocol from a `Sec-WebSocket-Protocol`
+    /// header. Returns None if the client offered none of ours.
+    pub fn negotiate(offered: &str) -> Option<Self> {
+        offered.split(',').map(str::trim).find_map(|name| match name {
+            "mio.json" => Some(WsEncoding::Json),
+            "mio.msgpack" => Some(WsEncoding::MsgPack),
+            "mio.cbor" => Some(WsEncoding::Cbor),
+            _ => None,
+        })
+    }
+
+    /// Encode a value as a frame for this encoding.
+    pub fn encode(&self, value: &Value) -> Message {
+        match self {
+            WsEncoding::Json => Message::text(value.to_string()),
+            WsEncoding::MsgPack => match rmp_serde::to_vec_named(value) {
+                Ok(bytes) => Message::binary(bytes),
+                Err(_) => Message::text(value.to_string()),
+            },
+            WsEncoding::Cbor => {
+                let mut bytes = Vec::new();
+                match ciborium::into_writer(value, &mut bytes) {
+                    Ok(()) => Message::binary(bytes),
+                    Err(_) => Message::text(value.to_string()),
+                }
+            }
+        }
+    }
+
+    /// Turn an inbound frame into the text the server handles: the line
+    /// itself for strings, JSON for maps. Returns None for frames that don't
+    /// decode.
+    pub fn decode(&self, msg: &Message) -> Option<String> {
+        let bytes = match msg {
+            Message::Text(text) => return Some(text.to_string()),
+            Message::Binary(bytes) => bytes,
+            _ => return None,
+        };
+        let value: Value = match self {
+            WsEncoding::Json => return String::from_utf8(bytes.to_vec()).ok(),
+            WsEncoding::MsgPack => rmp_serde::from_slice(bytes).ok()?,
+            WsEncoding::Cbor => ciborium::from_reader(bytes.as_ref()).ok()?,
+        };
+        match value {
+            Value::String(line) => Some(line),
+            other => Some(other.to_string()),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Static files and the built-in dashboard, served over plain HTTP on the
+//! WebSocket port.
+//!
+//! Files come from `websocket.static_dir`. The dashboard lists every
+//! broadcast id with its live value and a sparkline, and sends protocol
+//! lines back over the WebSocket.
+
+use super::websocket::percent_decode;
+use std::path::{Component, Path, PathBuf};
+
+/// The built-in dashboard page.
+pub const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+/// URL path the dashboard is always reachable at.
+pub const DASHBOARD_PATH: &str = "/dashboard";
+
+/// Map a URL path to a file under `root`. Directories resolve to their
+/// `index.html`. Returns None for paths that escape `root` or don't exist.
+pub fn resolve(root: &Path, url_path: &str) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    for component in Path::new(&percent_decode(url_path)).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    if path.is_dir() {
+        path.push("index.html");
+    }
+    path.is_file().then_some(path)
+}
+
+/// Content-Type for a file, from its extension.
+pub fn content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
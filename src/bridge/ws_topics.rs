@@ -0,0 +1,120 @@
+//! Per-client topic filtering for WebSocket broadcasts.
+//!
+//! Every broadcast has a topic: its id for `ws:<id>,<value>`, or `raw` for
+//! `ws:raw,...`. A client starts with the topics from its URL
+//! (`ws://host:8080/?topics=pot,temp*` or `ws://host:8080/pot,temp*`) and
+//! can change them with `{"subscribe":[...]}` / `{"unsubscribe":[...]}`.
+//! A pattern ending in `*` matches every topic with that prefix.
+
+use super::websocket::percent_decode;
+use std::collections::BTreeSet;
+
+/// The topics one client wants. Without a filter it receives everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TopicFilter {
+    patterns: Option<BTreeSet<String>>,
+}
+
+impl TopicFilter {
+    /// Build the initial filter from the handshake request path and query.
+    pub fn from_request(path: &str, query: Option<&str>) -> Self {
+        let mut filter = Self::default();
+
+        let path = path.trim_matches('/');
+        if !path.is_empty() {
+            filter.subscribe(split_topics(&percent_decode(path)));
+        }
+        for pair in query.unwrap_or("").split('&') {
+            if let Some(topics) = pair.strip_prefix("topics=") {
+                filter.subscribe(split_topics(&percent_decode(topics)));
+            }
+        }
+        filter
+    }
+
+    /// Add patterns. The first subscription narrows a client from
+    /// receiving everything to receiving only the subscribed topics.
+    pub fn subscribe<I, S>(&mut self, patterns: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.patterns
+            .get_or_insert_with(BTreeSet::new)
+            .extend(patterns.into_iter().map(Into::into));
+    }
+
+    /// Remove patterns. Unsubscribing from everything leaves the client
+    /// receiving nothing until it subscribes again.
+    pub fn unsubscribe<I, S>(&mut self, patterns: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        if let Some(set) = &mut self.patterns {
+            for pattern in patterns {
+                set.remove(pattern.as_ref());
+            }
+        }
+    }
+
+    /// Whether a message with `topic` should be sent to this client.
+    pub fn matches(&self, topic: &str) -> bool {
+        let Some(patterns) = &self.patterns else {
+            return true;
+        };
+        patterns.iter().any(|p| match p.strip_suffix('*') {
+            Some(prefix) => topic.starts_with(prefix),
+            None => p == topic,
+        })
+    }
+
+    /// Short description for display, e.g. `all` or `pot, temp*`.
+    pub fn describe(&self) -> String {
+        match &self.patterns {
+            None => "all".into(),
+            Some(patterns) if patterns.is_empty() => "none".into(),
+            Some(patterns) => patterns.iter().cloned().collect::<Vec<_>>().join(", "),
+        }
+    }
+
+    /// Handle a `{"subscribe":...}` / `{"unsubscribe":...}` control message.
+    /// Values may be a single topic or an array. Returns false if the text
+    /// is not such a message.
+    pub fn apply_control(&mut self, text: &str) -> bool {
+        if !text.trim_start().starts_with('{') {
+            return false;
+        }
+        let Ok(serde_json::Value::Object(obj)) = serde_json::from_str(text) else {
+            return false;
+        };
+        let subscribe = obj.get("subscribe").map(topic_list);
+        let unsubscribe = obj.get("unsubscribe").map(topic_list);
+        if subscribe.is_none() && unsubscribe.is_none() {
+            return false;
+        }
+        if let Some(topics) = unsubscribe {
+            self.unsubscribe(topics);
+        }
+        if let Some(topics) = subscribe {
+            self.subscribe(topics);
+        }
+        true
+    }
+}
+
+fn topic_list(value: &serde_json::Value) -> Vec<String> {
+    match value {
+        serde_json::Value::String(s) => vec![s.clone()],
+        serde_json::Value::Array(items) => items.iter().filter_map(|v| v.as_str()).map(String::from).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn split_topics(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(String::from)
+        .collect()
+}
@@ -4,10 +4,11 @@
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 /// Top-level configuration, mirrors the `mio.toml` file structure.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Config {
     pub serial: SerialConfig,
@@ -18,6 +19,21 @@ pub struct Config {
     pub websocket: WebSocketConfig,
     pub osc: OscConfig,
     pub tui: TuiConfig,
+    /// Noise filters per broadcast id or OSC address, applied before transforms.
+    pub filters: BTreeMap<String, Vec<Filter>>,
+    /// Value transforms per broadcast id or OSC address, applied before mappings.
+    pub transforms: BTreeMap<String, Vec<Transform>>,
+    /// Rules that turn matching commands into other commands, checked in order.
+    pub mappings: Vec<MappingConfig>,
+    /// Threshold triggers that fire commands when a value crosses them.
+    pub triggers: Vec<TriggerConfig>,
+    pub script: ScriptConfig,
+    /// Key names to send instead of others in `key:` commands, e.g. `a = "space"`.
+    pub keys: BTreeMap<String, String>,
+    /// Profile to start with (overridden by `--profile`).
+    pub profile: Option<String>,
+    /// Named profiles, switched with `profile:<name>`, the TUI or `--profile`.
+    pub profiles: BTreeMap<String, ProfileConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,12 +53,16 @@ pub struct ProtocolConfig {
 #[serde(default)]
 pub struct KeyboardConfig {
     pub enabled: bool,
+    /// Record key presses instead of typing them (as with `--dry-run`).
+    pub simulate: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct MouseConfig {
     pub enabled: bool,
+    /// Record mouse actions instead of performing them.
+    pub simulate: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,6 +70,8 @@ pub struct MouseConfig {
 pub struct MidiConfig {
     pub enabled: bool,
     pub auto_connect: bool,
+    /// Record MIDI messages instead of opening an output port.
+    pub simulate: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,6 +80,52 @@ pub struct WebSocketConfig {
     pub enabled: bool,
     pub port: u16,
     pub host: String,
+    /// Answer plain HTTP requests with the OSCQuery namespace of OSC addresses.
+    pub oscquery: bool,
+    /// Serve files from this directory over plain HTTP on the same port.
+    pub static_dir: Option<String>,
+    /// Serve the built-in dashboard at `/dashboard` (and at `/` for browsers).
+    pub dashboard: bool,
+    /// Shared token clients must present before they are served.
+    pub token: Option<String>,
+    /// Other origins browsers may connect from (`"*"` for any). Pages mio
+    /// serves itself are always allowed.
+    pub allowed_origins: Vec<String>,
+    /// Command prefixes clients may send inbound. Empty allows everything.
+    pub allowed_commands: Vec<String>,
+    /// Clients with their own token and permissions.
+    pub clients: Vec<WsClientConfig>,
+    /// PEM certificate chain and private key. When both are set the server speaks TLS (wss://).
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    /// Broadcasts buffered per client before it counts as lagging.
+    pub channel_capacity: usize,
+    /// What to do with a client that falls more than `channel_capacity` behind.
+    pub lag_policy: WsLagPolicy,
+    /// Ping clients this often (0 disables the heartbeat).
+    pub ping_interval_ms: u64,
+    /// Drop clients that send nothing (not even a pong) for this long.
+    pub client_timeout_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WsLagPolicy {
+    /// Drop the missed messages, tell the client how many, and carry on with the latest.
+    #[default]
+    Skip,
+    /// Close the connection; the client can reconnect and get the cached values.
+    Disconnect,
+}
+
+/// A WebSocket client identified by its token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WsClientConfig {
+    pub name: String,
+    pub token: String,
+    /// Command prefixes this client may send inbound. Empty allows everything.
+    #[serde(default)]
+    pub allowed_commands: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,6 +136,213 @@ pub struct OscConfig {
     pub local_port: u16,
     pub remote_address: String,
     pub remote_port: u16,
+    /// Named destinations. When empty, `remote_address:remote_port` is used for everything.
+    pub destinations: Vec<OscDestinationConfig>,
+    /// Group `osc:` lines arriving within this window into one bundle per destination.
+    /// 0 sends every message immediately.
+    pub bundle_window_ms: u64,
+    /// Timetag for bundles: 0 means "immediately", otherwise now + this delay.
+    pub bundle_delay_ms: u64,
+    /// Per-address argument types for untagged args, e.g. `"/layer1/clip" = "ii"`.
+    /// Keys ending in `*` match by prefix.
+    pub types: BTreeMap<String, String>,
+    pub receive: OscReceiveConfig,
+}
+
+/// A named OSC output destination.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OscDestinationConfig {
+    pub name: String,
+    pub address: String,
+    pub port: u16,
+    #[serde(default)]
+    pub mode: OscSendMode,
+    #[serde(default)]
+    pub transport: OscTransport,
+    /// Only addresses starting with one of these prefixes are sent here.
+    /// Empty means every address.
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OscSendMode {
+    #[default]
+    Unicast,
+    Broadcast,
+    Multicast,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OscTransport {
+    #[default]
+    Udp,
+    /// OSC 1.1 stream transport: SLIP-framed packets over a TCP connection.
+    Tcp,
+}
+
+/// Handling of OSC messages arriving on `local_address:local_port`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OscReceiveConfig {
+    pub enabled: bool,
+    /// Also accept OSC 1.1 TCP (SLIP-framed) clients on `local_address:local_port`.
+    pub tcp: bool,
+    /// Addresses under this prefix are translated to protocol lines:
+    /// `/mio/key/tap "a"` -> `key:tap,a`. `/mio/line "..."` passes a raw line.
+    pub command_prefix: String,
+    /// Explicit address routes, checked in order before `command_prefix`.
+    pub routes: Vec<OscRoute>,
+    /// What to do with messages that match neither a route nor `command_prefix`.
+    pub unmapped: OscForward,
+}
+
+/// Route incoming OSC messages matching `address` to a destination.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OscRoute {
+    /// Exact address, or a prefix when it ends with `*` (e.g. `/touchosc/*`).
+    pub address: String,
+    pub to: OscForward,
+    /// Protocol line template. Placeholders: `{address}`, `{args}` (comma-joined)
+    /// and `{0}`, `{1}`, ... for single arguments. Defaults depend on `to`.
+    #[serde(default)]
+    pub line: Option<String>,
+}
+
+/// Destination for an incoming OSC message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OscForward {
+    /// Parse the rendered line as a protocol command and dispatch it.
+    Command,
+    /// Broadcast to WebSocket clients (default line: `ws:{address},{args}`).
+    Ws,
+    /// Write the rendered line to the connected serial device
+    /// (default line: `osc:{address},{args}`).
+    Serial,
+    Ignore,
+}
+
+/// A `[[mappings]]` rule. Every condition that is set must match; the first
+/// matching rule replaces the command with the `emit` lines.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MappingConfig {
+    /// Line prefix, e.g. `ws:button1,` or `midi:cc`.
+    pub prefix: Option<String>,
+    /// Broadcast id of `ws:<id>,...`, exact or a prefix when it ends with `*`.
+    pub id: Option<String>,
+    /// OSC address of `osc:<address>,...`, exact or a prefix when it ends with `*`.
+    pub address: Option<String>,
+    /// Lowest accepted value (inclusive). The value is the first number of the command.
+    pub min: Option<f64>,
+    /// Highest accepted value (inclusive).
+    pub max: Option<f64>,
+    /// Only lines from this source: `serial`, `ws`, `osc` or `http`.
+    pub source: Option<String>,
+    /// Transforms for the value before it is put into `emit` lines.
+    pub transform: Vec<Transform>,
+    /// Protocol line templates to dispatch instead. Placeholders: `{line}`,
+    /// `{id}`, `{address}`, `{value}`, `{args}` (comma-joined) and `{0}`, `{1}`, ...
+    pub emit: Vec<String>,
+    /// Also dispatch the original command.
+    pub passthrough: bool,
+}
+
+/// A `[[triggers]]` rule: fires commands when a numeric value crosses a
+/// threshold. Every matching trigger sees the value, and the value itself is
+/// still dispatched (and mapped) as usual.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TriggerConfig {
+    /// Broadcast id of `ws:<id>,...`, exact or a prefix when it ends with `*`.
+    pub id: Option<String>,
+    /// OSC address of `osc:<address>,...`, exact or a prefix when it ends with `*`.
+    pub address: Option<String>,
+    /// Only values from this source: `serial`, `ws`, `osc` or `http`.
+    pub source: Option<String>,
+    /// Active while the value is above this (rising edge fires).
+    pub above: Option<f64>,
+    /// Active while the value is below this (falling edge fires).
+    pub below: Option<f64>,
+    /// How far the value has to go back past the threshold to release.
+    pub hysteresis: f64,
+    /// Don't fire again until this long after the last time.
+    pub min_interval_ms: u64,
+    /// Protocol lines to dispatch when the trigger fires, with the same
+    /// placeholders as `[[mappings]]` `emit`.
+    pub emit: Vec<String>,
+    /// Protocol lines to dispatch when the trigger releases.
+    pub release: Vec<String>,
+    /// Key to hold down while the trigger is active (`key:down` when it
+    /// fires, `key:up` when it releases).
+    pub hold: Option<String>,
+    /// Fire `emit` again every this many milliseconds while active (0 = off).
+    pub repeat_ms: u64,
+}
+
+/// One stage of a value transform, written as a one-key table in TOML,
+/// e.g. `{ clamp = [0, 127] }`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    /// Linear map between ranges: `{ map = { from = [0, 1023], to = [0, 127] } }`.
+    Map { from: [f64; 2], to: [f64; 2] },
+    /// Limit to a range: `{ clamp = [0, 127] }`.
+    Clamp([f64; 2]),
+    /// Mirror within a range: `{ invert = [0, 1023] }` turns 0 into 1023.
+    Invert([f64; 2]),
+    /// Exponential curve over 0..1, steeper for larger factors: `{ exp = 3 }`.
+    Exp(f64),
+    /// Logarithmic curve over 0..1: `{ log = 9 }`.
+    Log(f64),
+    /// Interpolate between `[input, output]` points sorted by input:
+    /// `{ lut = [[0, 0], [512, 100], [1023, 127]] }`.
+    Lut(Vec<[f64; 2]>),
+    /// Round to a multiple of a step: `{ quantize = 1 }`.
+    Quantize(f64),
+}
+
+/// One stage of a noise filter, e.g. `{ ema = 0.3 }` or `"change"`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    /// Exponential moving average; each new value is weighted by the factor: `{ ema = 0.3 }`.
+    Ema(f64),
+    /// Median of the last N values: `{ median = 5 }`.
+    Median(usize),
+    /// Drop values closer than this to the last forwarded one: `{ deadband = 2 }`.
+    Deadband(f64),
+    /// At most one value per interval, sending the latest when it ends: `{ throttle_ms = 50 }`.
+    ThrottleMs(u64),
+    /// Only forward values that differ from the last forwarded one.
+    Change,
+}
+
+/// A named profile. Whatever is set replaces the base config while the
+/// profile is active; the rest stays as it is.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileConfig {
+    pub mappings: Option<Vec<MappingConfig>>,
+    pub keys: Option<BTreeMap<String, String>>,
+    /// Replaces `[[osc.destinations]]` (or `remote_address:remote_port`).
+    pub osc_destinations: Option<Vec<OscDestinationConfig>>,
+    pub keyboard: Option<bool>,
+    pub mouse: Option<bool>,
+    pub midi: Option<bool>,
+    pub websocket: Option<bool>,
+    pub osc: Option<bool>,
+}
+
+/// The Rhai routing script, see `routing::script`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptConfig {
+    /// Path to the script, reloaded when it changes.
+    pub path: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -79,21 +354,6 @@ pub struct TuiConfig {
 
 // --- Defaults ---
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            serial: SerialConfig::default(),
-            protocol: ProtocolConfig::default(),
-            keyboard: KeyboardConfig::default(),
-            mouse: MouseConfig::default(),
-            midi: MidiConfig::default(),
-            websocket: WebSocketConfig::default(),
-            osc: OscConfig::default(),
-            tui: TuiConfig::default(),
-        }
-    }
-}
-
 impl Default for SerialConfig {
     fn default() -> Self {
         Self {
@@ -113,13 +373,19 @@ impl Default for ProtocolConfig {
 
 impl Default for KeyboardConfig {
     fn default() -> Self {
-        Self { enabled: true }
+        Self {
+            enabled: true,
+            simulate: false,
+        }
     }
 }
 
 impl Default for MouseConfig {
     fn default() -> Self {
-        Self { enabled: true }
+        Self {
+            enabled: true,
+            simulate: false,
+        }
     }
 }
 
@@ -128,6 +394,7 @@ impl Default for MidiConfig {
         Self {
             enabled: true,
             auto_connect: false,
+            simulate: false,
         }
     }
 }
@@ -138,6 +405,19 @@ impl Default for WebSocketConfig {
             enabled: true,
             port: 8080,
             host: "0.0.0.0".into(),
+            oscquery: true,
+            static_dir: None,
+            dashboard: true,
+            token: None,
+            allowed_origins: Vec::new(),
+            allowed_commands: Vec::new(),
+            clients: Vec::new(),
+            tls_cert: None,
+            tls_key: None,
+            channel_capacity: 256,
+            lag_policy: WsLagPolicy::Skip,
+            ping_interval_ms: 10000,
+            client_timeout_ms: 30000,
         }
     }
 }
@@ -150,6 +430,23 @@ impl Default for OscConfig {
             local_port: 7000,
             remote_address: "127.0.0.1".into(),
             remote_port: 7001,
+            destinations: Vec::new(),
+            bundle_window_ms: 0,
+            bundle_delay_ms: 0,
+            types: BTreeMap::new(),
+            receive: OscReceiveConfig::default(),
+        }
+    }
+}
+
+impl Default for OscReceiveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tcp: false,
+            command_prefix: "/mio".into(),
+            routes: Vec::new(),
+            unmapped: OscForward::Ignore,
         }
     }
 }
@@ -163,10 +460,29 @@ impl Default for TuiConfig {
     }
 }
 
+impl OscConfig {
+    /// The configured destinations, or a single catch-all "default"
+    /// destination built from `remote_address:remote_port`.
+    pub fn effective_destinations(&self) -> Vec<OscDestinationConfig> {
+        if !self.destinations.is_empty() {
+            return self.destinations.clone();
+        }
+        vec![OscDestinationConfig {
+            name: "default".into(),
+            address: self.remote_address.clone(),
+            port: self.remote_port,
+            mode: OscSendMode::Unicast,
+            transport: OscTransport::Udp,
+            prefixes: Vec::new(),
+        }]
+    }
+}
+
 /// Find the config file path using the search order:
 /// 1. Explicit --config path
 /// 2. ./mio.toml
 /// 3. ~/.config/mio/mio.toml
+///
 /// Returns None if no config file is found (defaults will be used).
 pub fn find_config_path(explicit: Option<&Path>) -> Option<PathBuf> {
     // 1. Explicit path from CLI
@@ -0,0 +1,253 @@
+//! Engine: the line processing shared by the TUI and headless modes.
+//!
+//! Owns the router, the routing layer, held-key tracking with its watchdog
+//! and the inputs (serial, WebSocket, OSC). Each `step` handles whatever
+//! arrived and returns what happened as `EngineEvent`s, which the front
+//! ends only have to render.
+
+use crate::bridge::midi::MidiBridge;
+use crate::bridge::osc::{DestinationStatus, OscBridge, OscInbound};
+use crate::bridge::websocket::WsInbound;
+use crate::bridge::{DispatchOutcome, Router};
+use crate::config::Config;
+use crate::protocol::{Command, Source};
+use crate::routing::{self, Routing};
+use crate::serial;
+use anyhow::Result;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Something the front end should show.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineEvent {
+    /// A line was handled, with what happened to each of its commands.
+    Line {
+        source: Source,
+        line: String,
+        outcomes: Vec<DispatchOutcome>,
+    },
+    /// A message for the user: watchdog releases, script output, bridge errors.
+    Info(String),
+    /// The serial device went away. Held keys were released.
+    SerialLost,
+}
+
+impl EngineEvent {
+    /// A line as logged: prefixed with its source unless it came from serial.
+    pub fn display_line(source: Source, line: &str) -> String {
+        match source {
+            Source::Serial => line.to_string(),
+            _ => format!("[{}] {}", source.as_str(), line),
+        }
+    }
+}
+
+pub struct Engine {
+    pub router: Router,
+    pub routing: Routing,
+    /// Keys held via `key:down`, released by the watchdog unless refreshed.
+    held_keys: Vec<String>,
+    /// Keys refreshed since the last watchdog tick.
+    keys_seen: Vec<String>,
+    watchdog_interval: Duration,
+    last_watchdog: Instant,
+    serial: Option<(serial::SerialHandle, mpsc::Receiver<String>)>,
+    ws_rx: tokio::sync::mpsc::Receiver<WsInbound>,
+    osc_rx: mpsc::Receiver<OscInbound>,
+    osc_links: Vec<DestinationStatus>,
+}
+
+impl Engine {
+    /// Build the engine and switch to the config's start profile.
+    pub fn new(
+        config: &Config,
+        mut router: Router,
+        ws_rx: tokio::sync::mpsc::Receiver<WsInbound>,
+        osc_rx: mpsc::Receiver<OscInbound>,
+    ) -> Result<Self> {
+        let mut routing = Routing::new(config);
+        if let Some(name) = &config.profile {
+            routing.switch_profile(&mut router, name)?;
+        }
+        Ok(Self {
+            router,
+            routing,
+            held_keys: Vec::new(),
+            keys_seen: Vec::new(),
+            watchdog_interval: Duration::from_millis(config.protocol.watchdog_interval_ms),
+            last_watchdog: Instant::now(),
+            serial: None,
+            ws_rx,
+            osc_rx,
+            osc_links: Vec::new(),
+        })
+    }
+
+    /// Open a serial port, replacing the current one.
+    pub fn connect_serial(&mut self, port_name: &str, baud_rate: u32) -> Result<()> {
+        self.disconnect_serial();
+        let (tx, rx) = mpsc::channel();
+        let handle = serial::spawn_reader(port_name, baud_rate, tx)?;
+        self.serial = Some((handle, rx));
+        self.routing.reset();
+        Ok(())
+    }
+
+    /// Close the serial port and release held keys.
+    pub fn disconnect_serial(&mut self) {
+        self.serial = None;
+        self.release_keys();
+    }
+
+    pub fn serial_connected(&self) -> bool {
+        self.serial.is_some()
+    }
+
+    /// Wait up to `wait` for serial input, then handle everything that
+    /// arrived, due timers and the watchdog.
+    pub fn step(&mut self, wait: Duration) -> Vec<EngineEvent> {
+        let mut events = Vec::new();
+
+        // --- Serial ---
+        let mut lines = Vec::new();
+        let mut lost = false;
+        match &self.serial {
+            Some((_, rx)) => {
+                match rx.recv_timeout(wait) {
+                    Ok(line) => lines.push(line),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => lost = true,
+                }
+                lines.extend(rx.try_iter());
+            }
+            None => std::thread::sleep(wait),
+        }
+        for line in lines {
+            if let Some(outcomes) = self.dispatch(&line, Source::Serial) {
+                events.push(EngineEvent::Line { source: Source::Serial, line, outcomes });
+            }
+        }
+
+        // --- WebSocket and HTTP ---
+        while let Ok(inbound) = self.ws_rx.try_recv() {
+            let outcomes = self.dispatch(&inbound.line, inbound.source);
+            let (source, line) = (inbound.source, inbound.line.clone());
+            inbound.respond(outcomes.as_deref().map(DispatchOutcome::join));
+            if let Some(outcomes) = outcomes {
+                events.push(EngineEvent::Line { source, line, outcomes });
+            }
+        }
+
+        // --- OSC ---
+        while let Ok(inbound) = self.osc_rx.try_recv() {
+            match inbound {
+                OscInbound::Dispatch(line) => {
+                    if let Some(outcomes) = self.dispatch(&line, Source::Osc) {
+                        events.push(EngineEvent::Line { source: Source::Osc, line, outcomes });
+                    }
+                }
+                OscInbound::Serial(line) => {
+                    let outcome = match &mut self.serial {
+                        Some((handle, _)) => match handle.write_line(&line) {
+                            Ok(()) => DispatchOutcome::ok("serial", "SERIAL write"),
+                            Err(e) => DispatchOutcome::error("serial", format!("SERIAL write ERROR: {}", e)),
+                        },
+                        None => DispatchOutcome::error("serial", "SERIAL write (not connected)"),
+                    };
+                    events.push(EngineEvent::Line { source: Source::Osc, line, outcomes: vec![outcome] });
+                }
+                OscInbound::Error(msg) => events.push(EngineEvent::Info(msg)),
+            }
+        }
+
+        // --- Held values, trigger repeats and script timers ---
+        let (held_keys, keys_seen) = (&mut self.held_keys, &mut self.keys_seen);
+        for (line, source, outcomes) in routing::flush(&mut self.router, &mut self.routing, |cmd| {
+            track_keys(held_keys, keys_seen, cmd)
+        }) {
+            events.push(EngineEvent::Line { source, line, outcomes });
+        }
+        events.extend(self.routing.take_log().into_iter().map(EngineEvent::Info));
+
+        // --- Buffered bridge output ---
+        events.extend(self.router.tick().into_iter().map(EngineEvent::Info));
+
+        // --- OSC link changes ---
+        if let Some(osc) = self.router.bridge::<OscBridge>() {
+            let links = osc.links();
+            for link in &links {
+                if !self.osc_links.contains(link) {
+                    events.push(EngineEvent::Info(format!("OSC {} ({}): {:?}", link.name, link.addr, link.state)));
+                }
+            }
+            self.osc_links = links;
+        }
+
+        // --- Watchdog ---
+        if self.last_watchdog.elapsed() >= self.watchdog_interval {
+            let stale: Vec<String> =
+                self.held_keys.iter().filter(|k| !self.keys_seen.contains(k)).cloned().collect();
+            if !stale.is_empty() {
+                self.router.release_all_keys(&stale);
+                self.held_keys.retain(|k| !stale.contains(k));
+                for key in stale {
+                    events.push(EngineEvent::Info(format!("WATCHDOG: released {}", key)));
+                }
+            }
+            self.keys_seen.clear();
+            self.last_watchdog = Instant::now();
+        }
+
+        if lost {
+            self.disconnect_serial();
+            events.push(EngineEvent::SerialLost);
+        }
+        events
+    }
+
+    /// Release held keys, finish a MIDI recording and shut the bridges
+    /// down. Call before exiting.
+    pub fn shutdown(&mut self) -> Result<Vec<EngineEvent>> {
+        self.release_keys();
+        let mut events = Vec::new();
+        if let Some(midi) = self.router.bridge_mut::<MidiBridge>() {
+            if let Some((path, count)) = midi.stop_recording()? {
+                events.push(EngineEvent::Info(format!(
+                    "MIDI recording saved: {} ({} events)",
+                    path.display(),
+                    count
+                )));
+            }
+        }
+        events.extend(self.router.shutdown().into_iter().map(EngineEvent::Info));
+        Ok(events)
+    }
+
+    /// Run a line through routing and dispatch it, tracking held keys.
+    fn dispatch(&mut self, line: &str, source: Source) -> Option<Vec<DispatchOutcome>> {
+        let (held_keys, keys_seen) = (&mut self.held_keys, &mut self.keys_seen);
+        routing::dispatch(&mut self.router, &mut self.routing, line, source, |cmd| {
+            track_keys(held_keys, keys_seen, cmd)
+        })
+    }
+
+    fn release_keys(&mut self) {
+        self.router.release_all_keys(&self.held_keys);
+        self.held_keys.clear();
+    }
+}
+
+fn track_keys(held_keys: &mut Vec<String>, keys_seen: &mut Vec<String>, cmd: &Command) {
+    match cmd {
+        Command::KeyDown(key) => {
+            if !held_keys.contains(key) {
+                held_keys.push(key.clone());
+            }
+            keys_seen.push(key.clone());
+        }
+        Command::KeyUp(key) => {
+            held_keys.retain(|k| k != key);
+        }
+        _ => {}
+    }
+}
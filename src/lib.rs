@@ -1,3 +1,10 @@
-//! Mio library — exposes modules for integration tests.
+//! Mio library — the bridges, routing and engine, for embedding mio in
+//! other programs (custom outputs implement `bridge::Bridge`) and for
+//! integration tests.
 
+pub mod bridge;
+pub mod config;
+pub mod engine;
 pub mod protocol;
+pub mod routing;
+pub mod serial;
@@ -7,15 +7,12 @@
 //! See `mio --help` for details.
 
 mod app;
-mod bridge;
-mod config;
 mod headless;
-mod protocol;
-mod serial;
 mod tui;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
+use mio_bridge::{bridge, config, engine, routing, serial};
 use std::path::PathBuf;
 
 /// Mio — Serial-to-Everything Bridge
@@ -49,6 +46,19 @@ struct Cli {
     /// WebSocket server port (overrides config)
     #[arg(long)]
     ws_port: Option<u16>,
+
+    /// Record all MIDI output to a Standard MIDI File, written on exit
+    #[arg(long, value_name = "PATH")]
+    record_midi: Option<PathBuf>,
+
+    /// Profile to start with (overrides config)
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// Log keyboard, mouse and MIDI output instead of performing it: each
+    /// line's result shows what would have been done
+    #[arg(long)]
+    dry_run: bool,
 }
 
 fn main() -> Result<()> {
@@ -92,44 +102,65 @@ fn main() -> Result<()> {
     if let Some(ws_port) = cli.ws_port {
         config.websocket.port = ws_port;
     }
+    if let Some(profile) = cli.profile {
+        config.profile = Some(profile);
+    }
+    if cli.dry_run {
+        config.keyboard.simulate = true;
+        config.mouse.simulate = true;
+        config.midi.simulate = true;
+    }
+    if let Some(name) = &config.profile {
+        if name != routing::profile::DEFAULT && !config.profiles.contains_key(name) {
+            anyhow::bail!("Unknown profile '{}'", name);
+        }
+    }
 
     // --- Build the tokio runtime (on a background thread) ---
     let runtime = tokio::runtime::Runtime::new()?;
 
     // --- Initialize the bridge router ---
-    let router = runtime.block_on(async { bridge::Router::new(&config) })?;
-
-    // --- Start WebSocket server if enabled ---
-    let (ws_client_count, ws_incoming_rx) = if config.websocket.enabled {
-        if let Some(ws_tx) = &router.ws_tx {
-            let (incoming_tx, incoming_rx) = tokio::sync::mpsc::channel(256);
-            let ws_tx_clone = ws_tx.clone();
-            let host = config.websocket.host.clone();
-            let port = config.websocket.port;
-
-            let (count, _handle) = runtime.block_on(async {
-                bridge::websocket::start_server(&host, port, ws_tx_clone, incoming_tx)
-                    .await
-                    .expect("Failed to start WebSocket server")
-            });
-
-            (Some(count), incoming_rx)
-        } else {
-            let (_tx, rx) = tokio::sync::mpsc::channel(1);
-            (None, rx)
+    let mut router = runtime.block_on(async { bridge::Router::new(&config) })?;
+
+    if let Some(path) = cli.record_midi {
+        match router.bridge_mut::<bridge::midi::MidiBridge>() {
+            Some(midi_bridge) => midi_bridge.start_recording(path)?,
+            None if config.midi.simulate => eprintln!("[mio] --record-midi ignored: MIDI output is simulated"),
+            None => eprintln!("[mio] --record-midi ignored: MIDI bridge is disabled"),
         }
+    }
+
+    // --- Start WebSocket server if enabled (in the config or any profile) ---
+    let ws = router.bridge::<bridge::websocket::WsBridge>().map(|ws| (ws.tx.clone(), ws.cache.clone()));
+    let (ws_clients, ws_incoming_rx) = if let Some((ws_tx, ws_cache)) = ws {
+        let (incoming_tx, incoming_rx) = tokio::sync::mpsc::channel(256);
+        let oscquery = match router.bridge::<bridge::osc::OscBridge>() {
+            Some(osc) if config.websocket.oscquery => Some(osc.namespace()),
+            _ => None,
+        };
+
+        let (clients, _handle) = runtime
+            .block_on(bridge::websocket::start_server(&config.websocket, ws_tx, incoming_tx, ws_cache, oscquery))
+            .context("Failed to start WebSocket server")?;
+
+        (Some(clients), incoming_rx)
     } else {
         let (_tx, rx) = tokio::sync::mpsc::channel(1);
         (None, rx)
     };
 
+    // --- Start OSC receiver if enabled ---
+    let (osc_tx, osc_incoming_rx) = std::sync::mpsc::channel();
+    let _osc_receiver = match router.bridge::<bridge::osc::OscBridge>() {
+        Some(osc) if config.osc.receive.enabled => Some(osc.spawn_receiver(&config.osc.receive, osc_tx)?),
+        _ => None,
+    };
+
     // --- Run the app ---
+    let mut engine = engine::Engine::new(&config, router, ws_incoming_rx, osc_incoming_rx)?;
     if cli.headless {
-        let (serial_tx, serial_rx) = std::sync::mpsc::channel();
-        let mut ws_incoming_rx = ws_incoming_rx;
-
-        let _serial_handle = if let Some(port_name) = &cli.port {
-            Some(serial::spawn_reader(port_name, config.serial.baud_rate, serial_tx)?)
+        if let Some(port_name) = &cli.port {
+            engine.connect_serial(port_name, config.serial.baud_rate)?;
         } else {
             println!("No --port specified. Use --port <name> in headless mode.");
             println!("Available ports:");
@@ -137,11 +168,11 @@ fn main() -> Result<()> {
                 println!("  {} ({})", port.name, port.port_type);
             }
             return Ok(());
-        };
+        }
 
-        headless::run(config, serial_rx, router, &mut ws_incoming_rx)?;
+        headless::run(engine)?;
     } else {
-        app::run(config, router, ws_client_count, ws_incoming_rx)?;
+        app::run(config, engine, ws_clients)?;
     }
 
     Ok(())
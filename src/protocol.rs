@@ -9,6 +9,7 @@
 //!   midi:note_on,60,127,0 -> MidiNoteOn { note: 60, velocity: 127, channel: 0 }
 //!   ws:temperature,23.5 -> WsBroadcast { id: "temperature", value: "23.5" }
 //!   osc:/sensor/temp,23.5 -> OscMessage { address: "/sensor/temp", args: ["23.5"] }
+//!   profile:show-a -> Profile("show-a")
 
 /// A parsed command from the serial protocol.
 #[derive(Debug, Clone, PartialEq)]
@@ -39,6 +40,35 @@ pub enum Command {
 
     // --- OSC ---
     OscMessage { address: String, args: Vec<String> },
+
+    // --- Profiles ---
+    /// Switch to a named profile (`default` for the base config).
+    Profile(String),
+}
+
+/// Where a protocol line came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Serial,
+    WebSocket,
+    Osc,
+    /// `POST /command` on the WebSocket port.
+    Http,
+    /// A timer in the routing script.
+    Script,
+}
+
+impl Source {
+    /// Short name used in logs and WebSocket messages.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Source::Serial => "serial",
+            Source::WebSocket => "ws",
+            Source::Osc => "osc",
+            Source::Http => "http",
+            Source::Script => "script",
+        }
+    }
 }
 
 /// Parse a single line from the serial port into a Command.
@@ -60,6 +90,7 @@ pub fn parse(line: &str) -> Option<Command> {
         "midi" => parse_midi(rest),
         "ws" => parse_ws(rest),
         "osc" => parse_osc(rest),
+        "profile" => parse_profile(rest),
         _ => None,
     }
 }
@@ -165,6 +196,15 @@ fn parse_ws(rest: &str) -> Option<Command> {
     Some(Command::WsBroadcast { id, value })
 }
 
+/// Parse a profile switch: profile:name
+fn parse_profile(rest: &str) -> Option<Command> {
+    let name = rest.trim();
+    if name.is_empty() || name.contains(',') {
+        return None;
+    }
+    Some(Command::Profile(name.to_string()))
+}
+
 /// Parse OSC commands: osc:/address,arg1,arg2,...
 fn parse_osc(rest: &str) -> Option<Command> {
     // The rest starts with the OSC address (e.g., /sensor/temp,23.5)
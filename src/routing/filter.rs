@@ -0,0 +1,155 @@
+//! Stateful filters for noisy sensor values.
+//!
+//! A chain of filters runs per ws: id or osc: address, before transforms:
+//!
+//! ```toml
+//! [filters]
+//! pot = [{ median = 3 }, { ema = 0.3 }, { deadband = 2 }, { throttle_ms = 50 }]
+//! ```
+//!
+//! Values with several arguments are filtered per argument. A throttled value
+//! is held and sent once its interval is over, so the last value always arrives.
+
+use crate::config::Filter;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// What a filter chain did with a value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterResult {
+    /// Forward these (possibly smoothed) values.
+    Pass(Vec<f64>),
+    /// Drop the value.
+    Drop,
+    /// Hold the value until the throttle interval ends, see [`FilterChain::flush`].
+    Hold,
+}
+
+/// The filters for one id, with their state.
+#[derive(Debug, Clone)]
+pub struct FilterChain {
+    stages: Vec<Filter>,
+    state: Vec<StageState>,
+    held: Option<Held>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct StageState {
+    /// Smoothed values (EMA) or the last forwarded values (deadband, change).
+    last: Vec<f64>,
+    /// Recent values per argument (median).
+    history: Vec<VecDeque<f64>>,
+    /// When the throttle last let a value through.
+    sent_at: Option<Instant>,
+}
+
+/// A value waiting at a throttle stage.
+#[derive(Debug, Clone)]
+struct Held {
+    stage: usize,
+    values: Vec<f64>,
+    due: Instant,
+}
+
+impl FilterChain {
+    pub fn new(stages: &[Filter]) -> Self {
+        Self {
+            stages: stages.to_vec(),
+            state: vec![StageState::default(); stages.len()],
+            held: None,
+        }
+    }
+
+    /// Forget all state, e.g. after the device reconnected.
+    pub fn reset(&mut self) {
+        self.state = vec![StageState::default(); self.stages.len()];
+        self.held = None;
+    }
+
+    /// Run a new value through the chain.
+    pub fn process(&mut self, values: Vec<f64>, now: Instant) -> FilterResult {
+        self.run(0, values, now)
+    }
+
+    /// When the held value is due, run it through the rest of the chain.
+    pub fn flush(&mut self, now: Instant) -> Option<FilterResult> {
+        if self.held.as_ref()?.due > now {
+            return None;
+        }
+        let held = self.held.take()?;
+        self.state[held.stage].sent_at = Some(now);
+        Some(self.run(held.stage + 1, held.values, now))
+    }
+
+    fn run(&mut self, from: usize, mut values: Vec<f64>, now: Instant) -> FilterResult {
+        for i in from..self.stages.len() {
+            let state = &mut self.state[i];
+            match self.stages[i] {
+                Filter::Ema(alpha) => {
+                    if state.last.len() == values.len() {
+                        for (last, x) in state.last.iter_mut().zip(&values) {
+                            *last += alpha * (x - *last);
+                        }
+                    } else {
+                        state.last = values.clone();
+                    }
+                    values = state.last.clone();
+                }
+                Filter::Median(n) => {
+                    state.history.resize_with(values.len(), VecDeque::new);
+                    for (history, x) in state.history.iter_mut().zip(values.iter_mut()) {
+                        history.push_back(*x);
+                        while history.len() > n.max(1) {
+                            history.pop_front();
+                        }
+                        *x = median(history);
+                    }
+                }
+                Filter::Deadband(band) => {
+                    let inside = state.last.len() == values.len()
+                        && state.last.iter().zip(&values).all(|(last, x)| (x - last).abs() < band);
+                    if inside {
+                        return FilterResult::Drop;
+                    }
+                    state.last = values.clone();
+                }
+                Filter::Change => {
+                    if state.last == values {
+                        return FilterResult::Drop;
+                    }
+                    state.last = values.clone();
+                }
+                Filter::ThrottleMs(ms) => {
+                    let interval = Duration::from_millis(ms);
+                    match state.sent_at {
+                        Some(sent) if now.duration_since(sent) < interval => {
+                            self.held = Some(Held {
+                                stage: i,
+                                values,
+                                due: sent + interval,
+                            });
+                            return FilterResult::Hold;
+                        }
+                        _ => {
+                            state.sent_at = Some(now);
+                            // A newer value replaces one still waiting
+                            self.held = None;
+                        }
+                    }
+                }
+            }
+        }
+        FilterResult::Pass(values)
+    }
+}
+
+fn median(values: &VecDeque<f64>) -> f64 {
+    let mut sorted: Vec<f64> = values.iter().copied().collect();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
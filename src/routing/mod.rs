@@ -0,0 +1,633 @@
+//! Routing layer between `protocol::parse` and `Router::dispatch`.
+//!
+//! `[[mappings]]` rules from the config turn a matching command into other
+//! commands, so one line from a board can drive several outputs:
+//!
+//! ```toml
+//! [[mappings]]
+//! id = "button1"
+//! min = 1
+//! emit = ["key:tap,space", "osc:/cue/go"]
+//! ```
+//!
+//! Rules are checked in order and the first match wins. Commands that match
+//! no rule are dispatched unchanged. Emitted commands are not mapped again.
+//!
+//! Before that, `[filters]` for the command's id or OSC address smooth or drop
+//! noisy values (see [`filter`]), then `[transforms]` rewrite the value (see
+//! [`transform`]). A rule's own `transform` applies to its `emit` lines.
+//! `[[triggers]]` watch the transformed value and add their own commands
+//! when it crosses a threshold (see [`trigger`]).
+//!
+//! A `[script]` sees every line before all of this and every command just
+//! before it is dispatched (see [`script`]). `[keys]` renames keys in the
+//! commands that are dispatched, and `profile:<name>` switches between
+//! `[profiles]` (see [`profile`]).
+
+pub mod filter;
+pub mod profile;
+pub mod script;
+pub mod template;
+pub mod transform;
+pub mod trigger;
+
+use crate::bridge::osc::OscBridge;
+use crate::bridge::{DispatchOutcome, Router};
+use crate::config::{Config, MappingConfig, Transform, TriggerConfig};
+use crate::protocol::{self, Command, Source};
+use anyhow::Result;
+use filter::{FilterChain, FilterResult};
+use profile::Profiles;
+use script::{Hook, Script};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::time::Instant;
+use trigger::{Edge, Trigger};
+
+/// The routing layer built from the config: per-id filters and transforms,
+/// mapping rules, triggers, the script and profiles.
+#[derive(Debug, Default)]
+pub struct Routing {
+    filters: BTreeMap<String, FilterChain>,
+    /// Values a throttle is holding back, per id.
+    held: BTreeMap<String, (Fields, Source)>,
+    transforms: BTreeMap<String, Vec<Transform>>,
+    mappings: Vec<MappingConfig>,
+    triggers: Vec<TriggerConfig>,
+    /// Trigger state per rule index and id.
+    trigger_state: BTreeMap<(usize, String), TriggerSlot>,
+    script: Option<Script>,
+    /// Key names to send instead of others.
+    keys: BTreeMap<String, String>,
+    profiles: Profiles,
+}
+
+/// A trigger's state with the last value it saw, for `repeat_ms`.
+#[derive(Debug, Clone)]
+struct TriggerSlot {
+    trigger: Trigger,
+    fields: Fields,
+    source: Source,
+}
+
+/// What a routed line turned into.
+pub type Routed = Vec<Result<Command, String>>;
+
+impl Routing {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            filters: config
+                .filters
+                .iter()
+                .map(|(key, stages)| (key.clone(), FilterChain::new(stages)))
+                .collect(),
+            held: BTreeMap::new(),
+            transforms: config.transforms.clone(),
+            mappings: config.mappings.clone(),
+            triggers: config.triggers.clone(),
+            trigger_state: BTreeMap::new(),
+            script: config.script.path.as_ref().map(Script::load),
+            keys: config.keys.clone(),
+            profiles: Profiles::new(config),
+        }
+    }
+
+    /// Switch to a named profile (`default` for the base config): its
+    /// mappings and key table here, its bridge enables and OSC destinations
+    /// on the router.
+    pub fn switch_profile(&mut self, router: &mut Router, name: &str) -> Result<()> {
+        let settings = self.profiles.switch(name)?;
+        self.mappings = settings.mappings;
+        self.keys = settings.keys;
+        router.enabled = settings.enabled;
+        match router.bridge_mut::<OscBridge>() {
+            Some(osc) => osc.set_destinations(&settings.osc_destinations),
+            None => Ok(()),
+        }
+    }
+
+    /// The active profile's name, `default` when none is.
+    pub fn active_profile(&self) -> &str {
+        self.profiles.active()
+    }
+
+    /// `default` followed by the configured profile names.
+    pub fn profile_names(&self) -> Vec<String> {
+        self.profiles.names()
+    }
+
+    /// Apply the `[keys]` table to a key command.
+    pub fn rename_key(&self, cmd: Command) -> Command {
+        let rename = |key: String| self.keys.get(&key).cloned().unwrap_or(key);
+        match cmd {
+            Command::KeyDown(key) => Command::KeyDown(rename(key)),
+            Command::KeyUp(key) => Command::KeyUp(rename(key)),
+            Command::KeyTap(key) => Command::KeyTap(rename(key)),
+            other => other,
+        }
+    }
+
+    /// Pass an incoming line to the script's `on_line`. Returns the line to
+    /// process (None if the script dropped it) and the lines it emitted.
+    pub fn script_line(&mut self, line: &str, source: Source) -> (Option<String>, Vec<String>) {
+        let Some(script) = &mut self.script else {
+            return (Some(line.to_string()), Vec::new());
+        };
+        let line = match script.on_line(source, line) {
+            Hook::Keep => Some(line.to_string()),
+            Hook::Replace(line) => Some(line),
+            Hook::Drop => None,
+        };
+        (line, script.take_emitted())
+    }
+
+    /// Pass a command to the script's `on_command`. Returns the command to
+    /// dispatch (None if the script dropped it, an error if its replacement
+    /// doesn't parse) and the lines it emitted.
+    pub fn script_command(&mut self, cmd: Command) -> (Option<Result<Command, String>>, Vec<String>) {
+        let Some(script) = &mut self.script else {
+            return (Some(Ok(cmd)), Vec::new());
+        };
+        let cmd = match script.on_command(&command_line(&cmd)) {
+            Hook::Keep => Some(Ok(cmd)),
+            Hook::Replace(line) => Some(protocol::parse(&line).ok_or(line)),
+            Hook::Drop => None,
+        };
+        (cmd, script.take_emitted())
+    }
+
+    /// Reload the script if it changed and run its due timers. Returns each
+    /// timer that fired with the lines it emitted.
+    pub fn script_tick(&mut self) -> Vec<(String, Vec<String>)> {
+        match &mut self.script {
+            Some(script) => script.tick(Instant::now()),
+            None => Vec::new(),
+        }
+    }
+
+    /// Messages from the script (errors, reloads, `print`) since the last call.
+    pub fn take_log(&mut self) -> Vec<String> {
+        match &mut self.script {
+            Some(script) => script.take_log(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Forget all filter state and serial triggers, e.g. after the device
+    /// reconnected. Keys those triggers held are left to the watchdog.
+    pub fn reset(&mut self) {
+        for chain in self.filters.values_mut() {
+            chain.reset();
+        }
+        self.held.clear();
+        self.trigger_state.retain(|_, slot| slot.source != Source::Serial);
+    }
+
+    /// The commands to dispatch for a parsed line, or None if a filter
+    /// dropped or held it. Lines that don't parse after mapping or
+    /// transforming are returned as errors.
+    pub fn apply(&mut self, line: &str, cmd: Command, source: Source) -> Option<Routed> {
+        let mut fields = Fields::of(line.trim(), &cmd);
+        let key = fields.key().map(String::from);
+        let chain = key.as_ref().and_then(|key| self.filters.get_mut(key));
+        let (Some(key), Some(chain), Some(values)) = (key.clone(), chain, fields.numbers()) else {
+            return Some(self.route(fields, Some(cmd), source));
+        };
+
+        match chain.process(values.clone(), Instant::now()) {
+            FilterResult::Pass(filtered) if filtered == values => Some(self.route(fields, Some(cmd), source)),
+            FilterResult::Pass(filtered) => {
+                fields.set_numbers(&filtered);
+                Some(self.route(fields, None, source))
+            }
+            FilterResult::Drop => None,
+            FilterResult::Hold => {
+                self.held.insert(key, (fields, source));
+                None
+            }
+        }
+    }
+
+    /// Route values held by a throttle whose interval is over, and fire
+    /// active triggers whose `repeat_ms` is due. Returns the original line,
+    /// its source and what it turned into.
+    pub fn flush(&mut self) -> Vec<(String, Source, Routed)> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for (key, chain) in &mut self.filters {
+            if let Some(result) = chain.flush(now) {
+                if let Some(held) = self.held.remove(key) {
+                    due.push((held, result));
+                }
+            }
+        }
+
+        let mut out = Vec::new();
+        for ((mut fields, source), result) in due {
+            let FilterResult::Pass(values) = result else {
+                continue;
+            };
+            if fields.numbers().as_ref() != Some(&values) {
+                fields.set_numbers(&values);
+            }
+            let line = fields.line.clone();
+            out.push((line, source, self.route(fields, None, source)));
+        }
+
+        for ((i, _), slot) in &mut self.trigger_state {
+            let rule = &self.triggers[*i];
+            if slot.trigger.repeat_due(rule, now) {
+                out.push((slot.fields.line.clone(), slot.source, slot.fields.emit(&rule.emit)));
+            }
+        }
+        out
+    }
+
+    /// The `hold` keys of active triggers, after the key table.
+    pub fn held_keys(&self) -> Vec<String> {
+        self.trigger_state
+            .iter()
+            .filter(|(_, slot)| slot.trigger.is_active())
+            .filter_map(|((i, _), _)| self.triggers[*i].hold.as_ref())
+            .map(|key| self.keys.get(key).unwrap_or(key).clone())
+            .collect()
+    }
+
+    /// Transform and map a (filtered) command. `cmd` is None when the fields
+    /// changed and the command has to be rebuilt from them.
+    fn route(&mut self, mut fields: Fields, mut cmd: Option<Command>, source: Source) -> Routed {
+        if let Some(stages) = fields.key().and_then(|key| self.transforms.get(key)) {
+            fields.transform(stages);
+            cmd = None;
+        }
+        let cmd = match cmd.map_or_else(|| fields.command(), Ok) {
+            Ok(cmd) => cmd,
+            Err(line) => return vec![Err(line)],
+        };
+
+        let triggered = self.trigger(&fields, source);
+        let mut out = match self.mappings.iter().find(|rule| fields.matches(rule, source)) {
+            Some(rule) => {
+                fields.transform(&rule.transform);
+                let mut out = fields.emit(&rule.emit);
+                if rule.passthrough {
+                    out.insert(0, Ok(cmd));
+                }
+                out
+            }
+            None => vec![Ok(cmd)],
+        };
+        out.extend(triggered);
+        out
+    }
+
+    /// Feed the value to every matching trigger and return what they fire.
+    fn trigger(&mut self, fields: &Fields, source: Source) -> Routed {
+        let (Some(key), Some(x)) = (fields.key(), fields.number()) else {
+            return Vec::new();
+        };
+        let now = Instant::now();
+        let mut out = Vec::new();
+        for (i, rule) in self.triggers.iter().enumerate() {
+            if !fields.matches_target(rule.id.as_deref(), rule.address.as_deref(), rule.source.as_deref(), source) {
+                continue;
+            }
+            let slot = self.trigger_state.entry((i, key.to_string())).or_insert_with(|| TriggerSlot {
+                trigger: Trigger::default(),
+                fields: fields.clone(),
+                source,
+            });
+            let edge = slot.trigger.update(rule, x, now);
+            slot.fields = fields.clone();
+            slot.source = source;
+
+            let hold = rule.hold.iter();
+            match edge {
+                Some(Edge::Enter) => {
+                    out.extend(hold.map(|key| Ok(Command::KeyDown(key.clone()))));
+                    out.extend(fields.emit(&rule.emit));
+                }
+                Some(Edge::Stay) => out.extend(hold.map(|key| Ok(Command::KeyDown(key.clone())))),
+                Some(Edge::Leave) => {
+                    out.extend(fields.emit(&rule.release));
+                    out.extend(hold.map(|key| Ok(Command::KeyUp(key.clone()))));
+                }
+                None => {}
+            }
+        }
+        out
+    }
+}
+
+/// Run a line from `source` through the script, the routing layer and the
+/// router. `before` is called with each command just before it is dispatched.
+/// Returns what happened to each command, or None if the line isn't a command.
+pub fn dispatch(
+    router: &mut Router,
+    routing: &mut Routing,
+    line: &str,
+    source: Source,
+    mut before: impl FnMut(&Command),
+) -> Option<Vec<DispatchOutcome>> {
+    let (line, emitted) = routing.script_line(line, source);
+    let mut outcomes = Vec::new();
+    match line.as_deref().map(|line| (line, protocol::parse(line))) {
+        Some((line, Some(cmd))) => match routing.apply(line, cmd, source) {
+            Some(routed) => outcomes.extend(dispatch_routed(router, routing, routed, source, &mut before)),
+            None => outcomes.push(DispatchOutcome::ok("filter", "FILTERED")),
+        },
+        Some((_, None)) if emitted.is_empty() => return None,
+        Some((_, None)) => {}
+        None => outcomes.push(DispatchOutcome::ok("script", "SCRIPT dropped")),
+    }
+    outcomes.extend(dispatch_emitted(router, routing, emitted, source, &mut before));
+    Some(outcomes)
+}
+
+/// Dispatch held values and trigger repeats that are due, and lines script
+/// timers emitted. Returns each line (`timer:<name>` for timers) and its
+/// source with what happened. The keys active triggers hold are passed to
+/// `before` as `key:down` without pressing them again, so the watchdog keeps
+/// them down while filters drop values or the sensor is slow.
+pub fn flush(
+    router: &mut Router,
+    routing: &mut Routing,
+    mut before: impl FnMut(&Command),
+) -> Vec<(String, Source, Vec<DispatchOutcome>)> {
+    let mut out: Vec<_> = routing
+        .flush()
+        .into_iter()
+        .map(|(line, source, routed)| {
+            let outcomes = dispatch_routed(router, routing, routed, source, &mut before);
+            (line, source, outcomes)
+        })
+        .collect();
+    for (timer, emitted) in routing.script_tick() {
+        if !emitted.is_empty() {
+            let outcomes = dispatch_emitted(router, routing, emitted, Source::Script, &mut before);
+            out.push((format!("timer:{}", timer), Source::Script, outcomes));
+        }
+    }
+    for key in routing.held_keys() {
+        before(&Command::KeyDown(key));
+    }
+    out
+}
+
+fn dispatch_routed(
+    router: &mut Router,
+    routing: &mut Routing,
+    routed: Routed,
+    source: Source,
+    mut before: impl FnMut(&Command),
+) -> Vec<DispatchOutcome> {
+    let mut outcomes = Vec::new();
+    for routed in routed {
+        let cmd = match routed {
+            Ok(cmd) => cmd,
+            Err(line) => {
+                outcomes.push(DispatchOutcome::error("map", format!("MAP ERROR: unrecognized line '{}'", line)));
+                continue;
+            }
+        };
+        let (cmd, emitted) = routing.script_command(cmd);
+        outcomes.push(match cmd {
+            Some(Ok(cmd)) => dispatch_command(router, routing, cmd, source, &mut before),
+            Some(Err(line)) => DispatchOutcome::error("script", format!("SCRIPT ERROR: unrecognized line '{}'", line)),
+            None => DispatchOutcome::ok("script", "SCRIPT dropped"),
+        });
+        outcomes.extend(dispatch_emitted(router, routing, emitted, source, &mut before));
+    }
+    if outcomes.is_empty() {
+        outcomes.push(DispatchOutcome::ok("map", "MAP (no output)"));
+    }
+    outcomes
+}
+
+/// Dispatch lines a script emitted, without routing them.
+fn dispatch_emitted(
+    router: &mut Router,
+    routing: &mut Routing,
+    lines: Vec<String>,
+    source: Source,
+    mut before: impl FnMut(&Command),
+) -> Vec<DispatchOutcome> {
+    lines
+        .into_iter()
+        .map(|line| match protocol::parse(&line) {
+            Some(cmd) => dispatch_command(router, routing, cmd, source, &mut before),
+            None => DispatchOutcome::error("script", format!("SCRIPT ERROR: unrecognized line '{}'", line)),
+        })
+        .collect()
+}
+
+/// Switch profiles, or rename keys and hand the command to the router.
+fn dispatch_command(
+    router: &mut Router,
+    routing: &mut Routing,
+    cmd: Command,
+    source: Source,
+    mut before: impl FnMut(&Command),
+) -> DispatchOutcome {
+    if let Command::Profile(name) = &cmd {
+        return match routing.switch_profile(router, name) {
+            Ok(()) => DispatchOutcome::ok("profile", format!("PROFILE {}", name)),
+            Err(e) => DispatchOutcome::error("profile", format!("PROFILE ERROR: {}", e)),
+        };
+    }
+    let cmd = routing.rename_key(cmd);
+    before(&cmd);
+    router.dispatch(&cmd, source)
+}
+
+/// The parts of a command rules match on and templates refer to.
+#[derive(Debug, Clone)]
+struct Fields {
+    line: String,
+    /// The command line up to its arguments, e.g. `midi:cc` or `ws:pot`.
+    head: String,
+    id: Option<String>,
+    address: Option<String>,
+    args: Vec<String>,
+    /// Which of `args` make up the value.
+    value: Range<usize>,
+}
+
+impl Fields {
+    fn of(line: &str, cmd: &Command) -> Self {
+        let (id, address) = match cmd {
+            Command::WsBroadcast { id, .. } => (Some(id.clone()), None),
+            Command::OscMessage { address, .. } => (None, Some(address.clone())),
+            _ => (None, None),
+        };
+        let (head, args) = command_parts(cmd);
+        let value = match cmd {
+            Command::MidiNoteOn { .. } | Command::MidiNoteOff { .. } | Command::MidiCc { .. } => 1..2,
+            _ => 0..args.len(),
+        };
+        Self {
+            line: line.to_string(),
+            head,
+            id,
+            address,
+            args,
+            value,
+        }
+    }
+
+    /// The id or address per-id transforms are looked up by.
+    fn key(&self) -> Option<&str> {
+        self.id.as_deref().or(self.address.as_deref())
+    }
+
+    /// The value text: the value arguments, comma-joined.
+    fn value(&self) -> String {
+        self.args.get(self.value.clone()).unwrap_or_default().join(",")
+    }
+
+    /// The value as a number: its first argument, if numeric.
+    fn number(&self) -> Option<f64> {
+        self.args.get(self.value.start)?.trim().parse().ok()
+    }
+
+    /// The value arguments as numbers, if they all are.
+    fn numbers(&self) -> Option<Vec<f64>> {
+        let args = self.args.get(self.value.clone()).filter(|args| !args.is_empty())?;
+        args.iter().map(|arg| arg.trim().parse().ok()).collect()
+    }
+
+    /// Replace the value arguments with filtered numbers.
+    fn set_numbers(&mut self, values: &[f64]) {
+        for (arg, x) in self.args.iter_mut().skip(self.value.start).zip(values) {
+            *arg = transform::format_number(*x);
+        }
+    }
+
+    /// Run every numeric value argument through `stages`.
+    fn transform(&mut self, stages: &[Transform]) {
+        if stages.is_empty() {
+            return;
+        }
+        let range = self.value.start.min(self.args.len())..self.value.end.min(self.args.len());
+        for arg in &mut self.args[range] {
+            *arg = transform::apply_to_arg(stages, arg);
+        }
+    }
+
+    /// Rebuild the command from its head and (transformed) arguments.
+    fn command(&self) -> Result<Command, String> {
+        let line = join_line(&self.head, &self.args);
+        protocol::parse(&line).ok_or(line)
+    }
+
+    fn matches(&self, rule: &MappingConfig, source: Source) -> bool {
+        if rule.prefix.as_deref().is_some_and(|p| !self.line.starts_with(p)) {
+            return false;
+        }
+        if !self.matches_target(rule.id.as_deref(), rule.address.as_deref(), rule.source.as_deref(), source) {
+            return false;
+        }
+        if rule.min.is_some() || rule.max.is_some() {
+            let Some(n) = self.number() else {
+                return false;
+            };
+            if rule.min.is_some_and(|min| n < min) || rule.max.is_some_and(|max| n > max) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Check a rule's `id`, `address` and `source` conditions, where set.
+    fn matches_target(&self, id: Option<&str>, address: Option<&str>, from: Option<&str>, source: Source) -> bool {
+        if let Some(pattern) = id {
+            if !self.id.as_deref().is_some_and(|id| pattern_matches(pattern, id)) {
+                return false;
+            }
+        }
+        if let Some(pattern) = address {
+            if !self.address.as_deref().is_some_and(|a| pattern_matches(pattern, a)) {
+                return false;
+            }
+        }
+        from.is_none_or(|s| s == source.as_str())
+    }
+
+    /// Render `emit` templates and parse them into commands.
+    fn emit(&self, templates: &[String]) -> Routed {
+        templates
+            .iter()
+            .map(|template| {
+                let line = self.render(template);
+                protocol::parse(&line).ok_or(line)
+            })
+            .collect()
+    }
+
+    /// Expand placeholders in an `emit` template. Unknown ones are kept as-is.
+    fn render(&self, template: &str) -> String {
+        template::render(template, &self.args, |name| match name {
+            "line" => Some(self.line.clone()),
+            "id" => Some(self.id.clone().unwrap_or_default()),
+            "address" => Some(self.address.clone().unwrap_or_default()),
+            "value" => Some(self.value()),
+            _ => None,
+        })
+    }
+}
+
+/// A command as a protocol line.
+pub fn command_line(cmd: &Command) -> String {
+    let (head, args) = command_parts(cmd);
+    join_line(&head, &args)
+}
+
+/// Split a command into the start of its protocol line and its arguments.
+fn command_parts(cmd: &Command) -> (String, Vec<String>) {
+    let (head, args) = match cmd {
+        Command::KeyDown(key) => ("key:down", vec![key.clone()]),
+        Command::KeyUp(key) => ("key:up", vec![key.clone()]),
+        Command::KeyTap(key) => ("key:tap", vec![key.clone()]),
+        Command::KeyType(text) => ("key:type", vec![text.clone()]),
+        Command::MouseMove { x, y } => ("mouse:move", vec![x.to_string(), y.to_string()]),
+        Command::MouseMoveRel { dx, dy } => ("mouse:move_rel", vec![dx.to_string(), dy.to_string()]),
+        Command::MouseClick(button) => ("mouse:click", vec![button.clone()]),
+        Command::MouseDown(button) => ("mouse:down", vec![button.clone()]),
+        Command::MouseUp(button) => ("mouse:up", vec![button.clone()]),
+        Command::MouseScroll { x, y } => ("mouse:scroll", vec![x.to_string(), y.to_string()]),
+        Command::MidiNoteOn { note, velocity, channel } => {
+            ("midi:note_on", vec![note.to_string(), velocity.to_string(), channel.to_string()])
+        }
+        Command::MidiNoteOff { note, velocity, channel } => {
+            ("midi:note_off", vec![note.to_string(), velocity.to_string(), channel.to_string()])
+        }
+        Command::MidiCc { controller, value, channel } => {
+            ("midi:cc", vec![controller.to_string(), value.to_string(), channel.to_string()])
+        }
+        Command::MidiRaw { bytes } => ("midi:raw", bytes.iter().map(|b| b.to_string()).collect()),
+        Command::WsBroadcast { id, value } => {
+            return (format!("ws:{}", id), value.split(',').map(String::from).collect());
+        }
+        Command::WsRaw(payload) => ("ws:raw", vec![payload.clone()]),
+        Command::OscMessage { address, args } => return (format!("osc:{}", address), args.clone()),
+        Command::Profile(name) => return (format!("profile:{}", name), Vec::new()),
+    };
+    (head.to_string(), args)
+}
+
+/// Put a protocol line back together from its head and arguments.
+fn join_line(head: &str, args: &[String]) -> String {
+    if args.is_empty() {
+        head.to_string()
+    } else {
+        format!("{},{}", head, args.join(","))
+    }
+}
+
+/// Match against an exact pattern or a `prefix*` pattern.
+fn pattern_matches(pattern: &str, value: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => pattern == value,
+    }
+}
@@ -0,0 +1,93 @@
+//! Named profiles: different mappings, key tables, OSC destinations and
+//! bridge enables per show, switched at runtime.
+//!
+//! ```toml
+//! [profiles.rehearsal]
+//! keyboard = false
+//! osc_destinations = [{ name = "desk", address = "127.0.0.1", port = 9000 }]
+//! ```
+//!
+//! Whatever a profile sets replaces the base config while it is active.
+//! `default` is the base config itself.
+
+use crate::bridge::BridgeEnables;
+use crate::config::{Config, MappingConfig, OscDestinationConfig, ProfileConfig};
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+
+/// The name of the base config as a profile.
+pub const DEFAULT: &str = "default";
+
+/// The profiles from the config and which one is active.
+#[derive(Debug, Clone, Default)]
+pub struct Profiles {
+    base: Settings,
+    profiles: BTreeMap<String, ProfileConfig>,
+    active: Option<String>,
+}
+
+/// Everything a profile can change.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub mappings: Vec<MappingConfig>,
+    pub keys: BTreeMap<String, String>,
+    pub osc_destinations: Vec<OscDestinationConfig>,
+    pub enabled: BridgeEnables,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::from_config(&Config::default())
+    }
+}
+
+impl Settings {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            mappings: config.mappings.clone(),
+            keys: config.keys.clone(),
+            osc_destinations: config.osc.effective_destinations(),
+            enabled: BridgeEnables::from_config(config),
+        }
+    }
+}
+
+impl Profiles {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            base: Settings::from_config(config),
+            profiles: config.profiles.clone(),
+            active: None,
+        }
+    }
+
+    /// `default` followed by the configured profile names.
+    pub fn names(&self) -> Vec<String> {
+        std::iter::once(DEFAULT.to_string()).chain(self.profiles.keys().cloned()).collect()
+    }
+
+    /// The active profile's name, `default` when none is.
+    pub fn active(&self) -> &str {
+        self.active.as_deref().unwrap_or(DEFAULT)
+    }
+
+    /// Make `name` the active profile and return its settings.
+    pub fn switch(&mut self, name: &str) -> Result<Settings> {
+        if name == DEFAULT {
+            self.active = None;
+            return Ok(self.base.clone());
+        }
+        let profile = self.profiles.get(name).ok_or_else(|| anyhow!("unknown profile '{}'", name))?;
+        let settings = Settings {
+            mappings: profile.mappings.clone().unwrap_or_else(|| self.base.mappings.clone()),
+            keys: profile.keys.clone().unwrap_or_else(|| self.base.keys.clone()),
+            osc_destinations: profile
+                .osc_destinations
+                .clone()
+                .unwrap_or_else(|| self.base.osc_destinations.clone()),
+            enabled: self.base.enabled.with_profile(profile),
+        };
+        self.active = Some(name.to_string());
+        Ok(settings)
+    }
+}
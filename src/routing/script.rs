@@ -0,0 +1,284 @@
+//! Rhai scripting hook for routing logic too complex for the config.
+//!
+//! The script at `[script] path` may define these functions:
+//!
+//! ```rhai
+//! fn init() { this.presses = 0; }            // after (re)loading
+//! fn on_line(source, line) { ... }           // every incoming line
+//! fn on_command(cmd) { ... }                 // every command before dispatch
+//! fn on_timer(name) { ... }                  // when a timer is due
+//! ```
+//!
+//! `on_line` and `on_command` return nothing to keep the line, a string to
+//! use that line instead, or `false` to drop it. `cmd` is the command as a
+//! protocol line. State kept in `this` lives until the script is reloaded.
+//!
+//! Scripts can call `emit(line)` to dispatch another protocol line (as is:
+//! it isn't mapped or passed to the hooks again), `set_timeout(name, ms)`,
+//! `set_interval(name, ms)`, `clear_timer(name)` and `print(text)`.
+//!
+//! The file is reloaded when it changes. Errors go to the log and never stop
+//! a line: a hook that fails keeps it unchanged.
+
+use crate::protocol::Source;
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the script file is checked for changes.
+const RELOAD_CHECK: Duration = Duration::from_millis(500);
+
+/// Operations one hook call may run before it is stopped.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// What a hook wants done with its line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hook {
+    Keep,
+    Replace(String),
+    Drop,
+}
+
+/// A loaded script and its state.
+pub struct Script {
+    path: PathBuf,
+    engine: Engine,
+    ast: Option<AST>,
+    /// Bound as `this` in every call.
+    state: Dynamic,
+    io: Arc<Mutex<ScriptIo>>,
+    modified: Option<SystemTime>,
+    checked_at: Instant,
+}
+
+/// What the script asked for through the functions registered with the engine.
+#[derive(Debug, Default)]
+struct ScriptIo {
+    emitted: Vec<String>,
+    log: Vec<String>,
+    timers: BTreeMap<String, Timer>,
+}
+
+#[derive(Debug, Clone)]
+struct Timer {
+    due: Instant,
+    every: Option<Duration>,
+}
+
+impl std::fmt::Debug for Script {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Script")
+            .field("path", &self.path)
+            .field("loaded", &self.ast.is_some())
+            .finish()
+    }
+}
+
+impl Script {
+    /// Load the script at `path`. A script that fails to load logs why and
+    /// does nothing until the file is fixed.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let io = Arc::new(Mutex::new(ScriptIo::default()));
+        let mut script = Self {
+            path: path.as_ref().to_path_buf(),
+            engine: engine(&io),
+            ast: None,
+            state: Dynamic::from_map(Map::new()),
+            io,
+            modified: None,
+            checked_at: Instant::now(),
+        };
+        script.reload();
+        script
+    }
+
+    /// Call `on_line(source, line)`.
+    pub fn on_line(&mut self, source: Source, line: &str) -> Hook {
+        self.hook("on_line", (source.as_str().to_string(), line.to_string()))
+    }
+
+    /// Call `on_command(cmd)` with the command as a protocol line.
+    pub fn on_command(&mut self, line: &str) -> Hook {
+        self.hook("on_command", (line.to_string(),))
+    }
+
+    /// Lines the script emitted since the last call.
+    pub fn take_emitted(&mut self) -> Vec<String> {
+        self.io.lock().map(|mut io| std::mem::take(&mut io.emitted)).unwrap_or_default()
+    }
+
+    /// Log lines (errors, reloads, `print`) since the last call.
+    pub fn take_log(&mut self) -> Vec<String> {
+        self.io.lock().map(|mut io| std::mem::take(&mut io.log)).unwrap_or_default()
+    }
+
+    /// Reload the file if it changed and call `on_timer` for due timers.
+    /// Returns each timer that fired with the lines it emitted.
+    pub fn tick(&mut self, now: Instant) -> Vec<(String, Vec<String>)> {
+        if now.duration_since(self.checked_at) >= RELOAD_CHECK {
+            self.checked_at = now;
+            if modified(&self.path) != self.modified {
+                self.reload();
+            }
+        }
+
+        let due: Vec<String> = match self.io.lock() {
+            Ok(mut io) => {
+                let due: Vec<String> =
+                    io.timers.iter().filter(|(_, t)| t.due <= now).map(|(name, _)| name.clone()).collect();
+                for name in &due {
+                    match io.timers.get(name).and_then(|t| t.every) {
+                        Some(every) => {
+                            if let Some(timer) = io.timers.get_mut(name) {
+                                timer.due = now + every;
+                            }
+                        }
+                        None => {
+                            io.timers.remove(name);
+                        }
+                    }
+                }
+                due
+            }
+            Err(_) => Vec::new(),
+        };
+
+        due.into_iter()
+            .map(|name| {
+                self.call("on_timer", (name.clone(),));
+                (name, self.take_emitted())
+            })
+            .collect()
+    }
+
+    fn reload(&mut self) {
+        self.modified = modified(&self.path);
+        match std::fs::read_to_string(&self.path) {
+            Ok(source) => {
+                let reloading = self.ast.is_some();
+                if self.compile(&source) {
+                    let verb = if reloading { "reloaded" } else { "loaded" };
+                    self.log(format!("SCRIPT: {} {}", verb, self.path.display()));
+                }
+            }
+            Err(e) => self.log(format!("SCRIPT ERROR: can't read {}: {}", self.path.display(), e)),
+        }
+    }
+
+    /// Compile and start a new version of the script. On errors the old
+    /// version keeps running.
+    fn compile(&mut self, source: &str) -> bool {
+        let ast = match self.engine.compile(source) {
+            Ok(ast) => ast,
+            Err(e) => {
+                self.log(format!("SCRIPT ERROR: {}", e));
+                return false;
+            }
+        };
+        self.ast = Some(ast);
+        self.state = Dynamic::from_map(Map::new());
+        if let Ok(mut io) = self.io.lock() {
+            io.timers.clear();
+            io.emitted.clear();
+        }
+        self.call("init", ());
+        true
+    }
+
+    fn hook(&mut self, name: &str, args: impl rhai::FuncArgs) -> Hook {
+        let Some(result) = self.call(name, args) else {
+            return Hook::Keep;
+        };
+        if result.is_unit() {
+            return Hook::Keep;
+        }
+        if let Ok(keep) = result.as_bool() {
+            return if keep { Hook::Keep } else { Hook::Drop };
+        }
+        if result.is_string() {
+            return Hook::Replace(result.to_string());
+        }
+        self.log(format!("SCRIPT ERROR: {} returned {}, expected a string or bool", name, result.type_name()));
+        Hook::Keep
+    }
+
+    /// Call a script function if it is defined. Errors are logged.
+    fn call(&mut self, name: &str, args: impl rhai::FuncArgs) -> Option<Dynamic> {
+        let ast = self.ast.as_ref()?;
+        if !ast.iter_functions().any(|f| f.name == name) {
+            return None;
+        }
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.state);
+        match self.engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), ast, name, args) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                self.log(format!("SCRIPT ERROR in {}: {}", name, e));
+                None
+            }
+        }
+    }
+
+    fn log(&self, line: String) {
+        if let Ok(mut io) = self.io.lock() {
+            io.log.push(line);
+        }
+    }
+}
+
+/// A sandboxed engine with the functions scripts can call.
+fn engine(io: &Arc<Mutex<ScriptIo>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_string_size(64 * 1024);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(10_000);
+    engine.disable_symbol("eval");
+
+    let shared = io.clone();
+    engine.on_print(move |text| {
+        if let Ok(mut io) = shared.lock() {
+            io.log.push(format!("SCRIPT: {}", text));
+        }
+    });
+    let shared = io.clone();
+    engine.register_fn("emit", move |line: &str| {
+        if let Ok(mut io) = shared.lock() {
+            io.emitted.push(line.to_string());
+        }
+    });
+    let shared = io.clone();
+    engine.register_fn("set_timeout", move |name: &str, ms: i64| {
+        set_timer(&shared, name, ms, false);
+    });
+    let shared = io.clone();
+    engine.register_fn("set_interval", move |name: &str, ms: i64| {
+        set_timer(&shared, name, ms, true);
+    });
+    let shared = io.clone();
+    engine.register_fn("clear_timer", move |name: &str| {
+        if let Ok(mut io) = shared.lock() {
+            io.timers.remove(name);
+        }
+    });
+    engine
+}
+
+fn set_timer(io: &Mutex<ScriptIo>, name: &str, ms: i64, repeat: bool) {
+    let interval = Duration::from_millis(ms.max(1) as u64);
+    if let Ok(mut io) = io.lock() {
+        io.timers.insert(
+            name.to_string(),
+            Timer {
+                due: Instant::now() + interval,
+                every: repeat.then_some(interval),
+            },
+        );
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
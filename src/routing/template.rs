@@ -0,0 +1,39 @@
+//! Placeholder expansion for line templates.
+//!
+//! Used by `[[mappings]]` `emit` lines and `[osc.receive]` templates:
+//! `{args}` is every argument comma-joined, `{N}` the Nth argument (empty if
+//! missing), and other names come from the caller. Unknown placeholders and
+//! an unclosed `{` are kept as-is.
+
+/// Expand the placeholders in `template`. `named` returns the text for
+/// names other than `args` and `N`, or None if it does not know them.
+pub fn render(template: &str, args: &[String], named: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let Some(close) = after.find('}') else {
+            out.push_str(&rest[open..]);
+            return out;
+        };
+        let name = &after[..close];
+        match name {
+            "args" => out.push_str(&args.join(",")),
+            _ => match name.parse::<usize>() {
+                Ok(i) => out.push_str(args.get(i).map(String::as_str).unwrap_or("")),
+                Err(_) => match named(name) {
+                    Some(text) => out.push_str(&text),
+                    None => {
+                        out.push('{');
+                        out.push_str(name);
+                        out.push('}');
+                    }
+                },
+            },
+        }
+        rest = &after[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,81 @@
+//! Numeric value transforms: scaling, clamping, curves, lookup tables.
+//!
+//! Stages run in order on every numeric value argument of a command, so a
+//! raw ADC reading can become a MIDI value without firmware math:
+//!
+//! ```toml
+//! [transforms]
+//! pot = [{ map = { from = [0, 1023], to = [0, 127] } }, { quantize = 1 }]
+//! ```
+
+use crate::config::Transform;
+
+impl Transform {
+    /// Apply this stage to one value.
+    pub fn apply(&self, x: f64) -> f64 {
+        match self {
+            Transform::Map { from, to } => {
+                let span = from[1] - from[0];
+                if span == 0.0 {
+                    return to[0];
+                }
+                to[0] + (x - from[0]) / span * (to[1] - to[0])
+            }
+            Transform::Clamp([min, max]) => x.max(min.min(*max)).min(max.max(*min)),
+            Transform::Invert([min, max]) => min + max - x,
+            Transform::Exp(k) if *k != 0.0 => ((k * x).exp() - 1.0) / (k.exp() - 1.0),
+            Transform::Log(k) if *k > 0.0 => (1.0 + k * x).ln() / (1.0 + k).ln(),
+            Transform::Exp(_) | Transform::Log(_) => x,
+            Transform::Lut(points) => lookup(points, x),
+            Transform::Quantize(step) if *step > 0.0 => (x / step).round() * step,
+            Transform::Quantize(_) => x,
+        }
+    }
+}
+
+/// Run a value through every stage.
+pub fn apply_all(stages: &[Transform], x: f64) -> f64 {
+    stages.iter().fold(x, |x, stage| stage.apply(x))
+}
+
+/// Transform a protocol argument if it is a number. Other text is kept.
+pub fn apply_to_arg(stages: &[Transform], arg: &str) -> String {
+    match arg.trim().parse::<f64>() {
+        Ok(x) if x.is_finite() && !stages.is_empty() => format_number(apply_all(stages, x)),
+        _ => arg.to_string(),
+    }
+}
+
+/// Write a number the way a protocol line expects it: whole numbers
+/// without a fraction, so `63.0` becomes `63` and still parses as MIDI.
+pub fn format_number(x: f64) -> String {
+    if x.fract() == 0.0 && x.abs() < 1e15 {
+        format!("{}", x as i64)
+    } else {
+        format!("{}", x)
+    }
+}
+
+/// Piecewise-linear interpolation between `[input, output]` points sorted
+/// by input. Values outside the table take the nearest end point.
+fn lookup(points: &[[f64; 2]], x: f64) -> f64 {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return x;
+    };
+    if x <= first[0] {
+        return first[1];
+    }
+    if x >= last[0] {
+        return last[1];
+    }
+    for pair in points.windows(2) {
+        let ([x0, y0], [x1, y1]) = (pair[0], pair[1]);
+        if x <= x1 {
+            if x1 == x0 {
+                return y1;
+            }
+            return y0 + (x - x0) / (x1 - x0) * (y1 - y0);
+        }
+    }
+    last[1]
+}
@@ -0,0 +1,94 @@
+//! Threshold triggers: turn a numeric stream into events.
+//!
+//! A trigger is active while the value is past its threshold and fires its
+//! `emit` lines when it becomes active and its `release` lines when it stops:
+//!
+//! ```toml
+//! [[triggers]]
+//! id = "dist"
+//! below = 30
+//! hysteresis = 5
+//! emit = ["key:tap,space"]
+//! ```
+//!
+//! `hysteresis` keeps a value jittering around the threshold from firing
+//! again and again: the trigger above only releases once `dist` is back at 35.
+
+use crate::config::TriggerConfig;
+use std::time::{Duration, Instant};
+
+/// How a value changed a trigger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    /// The value crossed the threshold: the trigger fires.
+    Enter,
+    /// The trigger was active and still is.
+    Stay,
+    /// The value went back past the threshold and the hysteresis.
+    Leave,
+}
+
+/// The state of one trigger rule for one id.
+#[derive(Debug, Clone, Default)]
+pub struct Trigger {
+    active: bool,
+    fired_at: Option<Instant>,
+    repeat_at: Option<Instant>,
+}
+
+impl TriggerConfig {
+    /// Whether a value is past the threshold. Without `above` or `below`
+    /// nothing is.
+    fn entered(&self, x: f64) -> bool {
+        if self.above.is_none() && self.below.is_none() {
+            return false;
+        }
+        self.above.is_none_or(|above| x > above) && self.below.is_none_or(|below| x < below)
+    }
+
+    /// Whether a value is back past the threshold by at least the hysteresis.
+    fn left(&self, x: f64) -> bool {
+        let band = self.hysteresis.abs();
+        self.above.is_some_and(|above| x <= above - band) || self.below.is_some_and(|below| x >= below + band)
+    }
+}
+
+impl Trigger {
+    /// Feed a new value. Returns None while the trigger is inactive, or when
+    /// it would fire again before `min_interval_ms` is over.
+    pub fn update(&mut self, rule: &TriggerConfig, x: f64, now: Instant) -> Option<Edge> {
+        if self.active {
+            if rule.left(x) {
+                self.active = false;
+                self.repeat_at = None;
+                return Some(Edge::Leave);
+            }
+            return Some(Edge::Stay);
+        }
+
+        let interval = Duration::from_millis(rule.min_interval_ms);
+        if !rule.entered(x) || self.fired_at.is_some_and(|at| now.duration_since(at) < interval) {
+            return None;
+        }
+        self.active = true;
+        self.fired_at = Some(now);
+        self.repeat_at = (rule.repeat_ms > 0).then(|| now + Duration::from_millis(rule.repeat_ms));
+        Some(Edge::Enter)
+    }
+
+    /// Whether the value is past the threshold.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Whether an active trigger with `repeat_ms` should fire again.
+    pub fn repeat_due(&mut self, rule: &TriggerConfig, now: Instant) -> bool {
+        match self.repeat_at {
+            Some(at) if self.active && at <= now => {
+                self.repeat_at = Some(now + Duration::from_millis(rule.repeat_ms));
+                true
+            }
+            _ => false,
+        }
+    }
+}
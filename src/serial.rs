@@ -2,9 +2,10 @@
 //!
 //! The serial reader runs in a dedicated std::thread (blocking I/O)
 //! and sends complete lines to the main event loop via mpsc channel.
+//! Lines can be written back to the device through the returned handle.
 
 use anyhow::{Context, Result};
-use std::io::BufRead;
+use std::io::{BufRead, Write};
 use std::sync::mpsc;
 use std::time::Duration;
 
@@ -55,6 +56,9 @@ pub fn spawn_reader(
         .timeout(Duration::from_millis(100))
         .open()
         .with_context(|| format!("Failed to open serial port: {}", port_name))?;
+    let writer = port
+        .try_clone()
+        .with_context(|| format!("Failed to clone serial port for writing: {}", port_name))?;
 
     let (stop_tx, stop_rx) = mpsc::channel::<()>();
     let port_name_owned = port_name.to_string();
@@ -77,11 +81,9 @@ pub fn spawn_reader(
                 }
                 Ok(_) => {
                     let trimmed = line_buf.trim().to_string();
-                    if !trimmed.is_empty() {
-                        if line_tx.send(trimmed).is_err() {
-                            // Receiver dropped, time to quit
-                            break;
-                        }
+                    if !trimmed.is_empty() && line_tx.send(trimmed).is_err() {
+                        // Receiver dropped, time to quit
+                        break;
                     }
                 }
                 Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {
@@ -99,6 +101,7 @@ pub fn spawn_reader(
     });
 
     Ok(SerialHandle {
+        writer,
         _stop_tx: stop_tx,
         _thread: handle,
     })
@@ -106,6 +109,17 @@ pub fn spawn_reader(
 
 /// Handle for a running serial reader. Dropping this signals the thread to stop.
 pub struct SerialHandle {
+    writer: Box<dyn serialport::SerialPort>,
     _stop_tx: mpsc::Sender<()>,
     _thread: std::thread::JoinHandle<()>,
 }
+
+impl SerialHandle {
+    /// Write a single `\n`-terminated line to the device.
+    pub fn write_line(&mut self, line: &str) -> Result<()> {
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -9,6 +9,10 @@ pub enum TuiAction {
     Quit,
     ToggleConnect,         // 'c' — connect/disconnect serial
     ToggleMidi,            // 'm' — connect/disconnect MIDI
+    ToggleRecord,          // 'r' — start/stop MIDI recording
+    ShowClients,           // 'w' — list WebSocket clients
+    Kick,                  // 'k' — disconnect the selected WebSocket client
+    ShowProfiles,          // 'p' — switch profile
     ScrollUp,              // Up arrow
     ScrollDown,            // Down arrow
     ShowHelp,              // '?'
@@ -38,6 +42,10 @@ fn map_key(key: KeyEvent) -> TuiAction {
         KeyCode::Char('q') => TuiAction::Quit,
         KeyCode::Char('c') => TuiAction::ToggleConnect,
         KeyCode::Char('m') => TuiAction::ToggleMidi,
+        KeyCode::Char('r') => TuiAction::ToggleRecord,
+        KeyCode::Char('w') => TuiAction::ShowClients,
+        KeyCode::Char('k') => TuiAction::Kick,
+        KeyCode::Char('p') => TuiAction::ShowProfiles,
         KeyCode::Char('?') => TuiAction::ShowHelp,
         KeyCode::Up => TuiAction::ScrollUp,
         KeyCode::Down => TuiAction::ScrollDown,
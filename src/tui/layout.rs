@@ -1,6 +1,8 @@
 //! TUI layout: status bar, log area, and footer.
 
 use crate::app::AppState;
+use crate::bridge::osc_tcp::LinkState;
+use crate::bridge::DispatchStatus;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
@@ -37,6 +39,19 @@ pub fn render(frame: &mut Frame, state: &AppState) {
                 let items: Vec<String> = ports.iter().map(|p| p.name.clone()).collect();
                 widgets::render_selection_popup(frame, "Select MIDI Port", &items, *selected);
             }
+            widgets::Popup::WsClients { clients, selected } => {
+                widgets::render_clients_popup(frame, clients, *selected);
+            }
+            widgets::Popup::Profiles { names, selected } => {
+                let items: Vec<String> = names
+                    .iter()
+                    .map(|name| match &state.profile {
+                        Some(active) if active == name => format!("{} (active)", name),
+                        _ => name.clone(),
+                    })
+                    .collect();
+                widgets::render_selection_popup(frame, "Switch Profile", &items, *selected);
+            }
             widgets::Popup::Help => {
                 widgets::render_help_popup(frame);
             }
@@ -45,8 +60,12 @@ pub fn render(frame: &mut Frame, state: &AppState) {
 }
 
 fn render_status(frame: &mut Frame, area: Rect, state: &AppState) {
+    let title = match &state.profile {
+        Some(profile) => format!("  Mio v{}  ·  profile: {}  ", env!("CARGO_PKG_VERSION"), profile),
+        None => format!("  Mio v{}  ", env!("CARGO_PKG_VERSION")),
+    };
     let block = Block::default()
-        .title(format!("  Mio v{}  ", env!("CARGO_PKG_VERSION")))
+        .title(title)
         .borders(Borders::ALL)
         .border_style(Style::default().fg(Color::DarkGray));
 
@@ -80,7 +99,7 @@ fn render_status(frame: &mut Frame, area: Rect, state: &AppState) {
     frame.render_widget(Paragraph::new(serial_status), status_lines[0]);
 
     // MIDI status
-    let midi_status = if let Some(name) = &state.midi_port_name {
+    let mut midi_status = if let Some(name) = &state.midi_port_name {
         Line::from(vec![
             Span::styled("  MIDI    ", Style::default().fg(Color::White)),
             Span::styled(format!("[{}]  ", name), Style::default().fg(Color::Magenta)),
@@ -92,16 +111,26 @@ fn render_status(frame: &mut Frame, area: Rect, state: &AppState) {
             Span::styled("○ DISCONNECTED", Style::default().fg(Color::DarkGray)),
         ])
     };
+    if state.midi_recording {
+        midi_status.push_span(Span::styled("  ● REC", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)));
+    }
     frame.render_widget(Paragraph::new(midi_status), status_lines[1]);
 
     // WebSocket status
     let ws_status = if state.ws_enabled {
         let clients = state.ws_client_count;
-        Line::from(vec![
+        let mut spans = vec![
             Span::styled("  WS      ", Style::default().fg(Color::White)),
             Span::styled(format!(":{} ({} clients)  ", state.ws_port, clients), Style::default().fg(Color::Yellow)),
             Span::styled("● LISTENING", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
-        ])
+        ];
+        if state.ws_dropped > 0 {
+            spans.push(Span::styled(
+                format!("  {} dropped", state.ws_dropped),
+                Style::default().fg(Color::Red),
+            ));
+        }
+        Line::from(spans)
     } else {
         Line::from(vec![
             Span::styled("  WS      ", Style::default().fg(Color::White)),
@@ -112,11 +141,32 @@ fn render_status(frame: &mut Frame, area: Rect, state: &AppState) {
 
     // OSC status
     let osc_status = if state.osc_enabled {
-        Line::from(vec![
+        let mut spans = vec![
             Span::styled("  OSC     ", Style::default().fg(Color::White)),
             Span::styled(format!("{}  ", state.osc_remote), Style::default().fg(Color::Blue)),
-            Span::styled("● READY", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
-        ])
+        ];
+        let down: Vec<_> = state.osc_links.iter().filter(|l| !l.state.is_up()).collect();
+        if down.is_empty() {
+            spans.push(Span::styled("● READY", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)));
+        } else {
+            let up = state.osc_links.len() - down.len();
+            spans.push(Span::styled(
+                format!("● {}/{} UP", up, state.osc_links.len()),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ));
+            for link in down {
+                let detail = match &link.state {
+                    LinkState::Connecting => "connecting".to_string(),
+                    LinkState::Disconnected(e) => format!("retrying: {}", e),
+                    LinkState::Ready | LinkState::Connected => continue,
+                };
+                spans.push(Span::styled(
+                    format!("  {} ({})", link.name, detail),
+                    Style::default().fg(Color::Red),
+                ));
+            }
+        }
+        Line::from(spans)
     } else {
         Line::from(vec![
             Span::styled("  OSC     ", Style::default().fg(Color::White)),
@@ -127,9 +177,15 @@ fn render_status(frame: &mut Frame, area: Rect, state: &AppState) {
 }
 
 fn render_log(frame: &mut Frame, area: Rect, state: &AppState) {
-    let block = Block::default()
+    let mut block = Block::default()
         .borders(Borders::ALL)
         .border_style(Style::default().fg(Color::DarkGray));
+    if state.dispatch_errors > 0 {
+        block = block.title(Span::styled(
+            format!(" {} errors ", state.dispatch_errors),
+            Style::default().fg(Color::Red),
+        ));
+    }
 
     let inner = block.inner(area);
     frame.render_widget(block, area);
@@ -160,10 +216,12 @@ fn render_log(frame: &mut Frame, area: Rect, state: &AppState) {
                 Style::default().fg(Color::White),
             ));
             spans.push(Span::raw("  ->  "));
-            spans.push(Span::styled(
-                &entry.result,
-                Style::default().fg(Color::Cyan),
-            ));
+            let color = match entry.status {
+                DispatchStatus::Ok => Color::Cyan,
+                DispatchStatus::Disabled => Color::DarkGray,
+                DispatchStatus::Error => Color::Red,
+            };
+            spans.push(Span::styled(&entry.result, Style::default().fg(color)));
             Line::from(spans)
         })
         .collect();
@@ -189,6 +247,7 @@ fn render_footer(frame: &mut Frame, area: Rect, state: &AppState) {
 
     let connect_label = if state.serial_connected { "Disconnect" } else { "Connect" };
     let midi_label = if state.midi_port_name.is_some() { "MIDI off" } else { "MIDI" };
+    let record_label = if state.midi_recording { "Stop rec" } else { "Record" };
 
     let footer = Line::from(vec![
         Span::styled("  [q]", Style::default().fg(Color::Yellow)),
@@ -197,6 +256,12 @@ fn render_footer(frame: &mut Frame, area: Rect, state: &AppState) {
         Span::raw(format!(" {}  ", connect_label)),
         Span::styled("[m]", Style::default().fg(Color::Yellow)),
         Span::raw(format!(" {}  ", midi_label)),
+        Span::styled("[r]", Style::default().fg(Color::Yellow)),
+        Span::raw(format!(" {}  ", record_label)),
+        Span::styled("[w]", Style::default().fg(Color::Yellow)),
+        Span::raw(" Clients  "),
+        Span::styled("[p]", Style::default().fg(Color::Yellow)),
+        Span::raw(" Profile  "),
         Span::styled("[↑↓]", Style::default().fg(Color::Yellow)),
         Span::raw(" Scroll  "),
         Span::styled("[?]", Style::default().fg(Color::Yellow)),
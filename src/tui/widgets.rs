@@ -1,6 +1,8 @@
 //! Custom TUI widgets: selection popups and help overlay.
 
 use crate::bridge::midi::MidiPortInfo;
+use crate::bridge::websocket::ClientInfo;
+use crate::bridge::ws_codec::WsEncoding;
 use crate::serial::PortInfo;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
@@ -21,6 +23,14 @@ pub enum Popup {
         ports: Vec<MidiPortInfo>,
         selected: usize,
     },
+    WsClients {
+        clients: Vec<ClientInfo>,
+        selected: usize,
+    },
+    Profiles {
+        names: Vec<String>,
+        selected: usize,
+    },
     Help,
 }
 
@@ -60,6 +70,45 @@ pub fn render_selection_popup(frame: &mut Frame, title: &str, items: &[String],
     frame.render_widget(list, area);
 }
 
+/// Render the WebSocket client list.
+pub fn render_clients_popup(frame: &mut Frame, clients: &[ClientInfo], selected: usize) {
+    let items: Vec<String> = if clients.is_empty() {
+        vec!["No clients connected".into()]
+    } else {
+        clients.iter().map(format_client).collect()
+    };
+    render_selection_popup(frame, "WebSocket Clients — [k] kick  [Esc] close", &items, selected);
+}
+
+fn format_client(client: &ClientInfo) -> String {
+    let name = client.name.as_deref().map(|n| format!(" {}", n)).unwrap_or_default();
+    let mut line = format!(
+        "#{} {}{}  up {}  in {} out {}  topics: {}",
+        client.id,
+        client.peer,
+        name,
+        format_uptime(client.connected_at.elapsed().as_secs()),
+        client.messages_in,
+        client.messages_out,
+        client.topics,
+    );
+    if client.encoding != WsEncoding::Json {
+        line.push_str(&format!("  {}", client.encoding.as_str()));
+    }
+    if client.dropped > 0 {
+        line.push_str(&format!("  dropped {}", client.dropped));
+    }
+    line
+}
+
+fn format_uptime(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
 /// Render the help popup.
 pub fn render_help_popup(frame: &mut Frame) {
     let area = centered_rect(60, 70, frame.area());
@@ -89,6 +138,18 @@ pub fn render_help_popup(frame: &mut Frame) {
             Span::styled("  [m]     ", Style::default().fg(Color::Yellow)),
             Span::raw("Connect/disconnect MIDI output"),
         ]),
+        Line::from(vec![
+            Span::styled("  [r]     ", Style::default().fg(Color::Yellow)),
+            Span::raw("Start/stop MIDI recording (.mid)"),
+        ]),
+        Line::from(vec![
+            Span::styled("  [w]     ", Style::default().fg(Color::Yellow)),
+            Span::raw("WebSocket clients ([k] to kick)"),
+        ]),
+        Line::from(vec![
+            Span::styled("  [p]     ", Style::default().fg(Color::Yellow)),
+            Span::raw("Switch profile"),
+        ]),
         Line::from(vec![
             Span::styled("  [↑/↓]   ", Style::default().fg(Color::Yellow)),
             Span::raw("Scroll log"),
@@ -116,6 +177,7 @@ pub fn render_help_popup(frame: &mut Frame) {
         Line::from("  midi:note_on,60,127,0  MIDI note on"),
         Line::from("  ws:temp,23.5       WebSocket broadcast"),
         Line::from("  osc:/addr,1.0      Send OSC message"),
+        Line::from("  profile:show-a     Switch profile"),
         Line::from(""),
         Line::from(Span::styled(
             "  Press [Esc] to close",
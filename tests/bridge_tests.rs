@@ -0,0 +1,156 @@
+//! Tests for the Bridge trait and the router's registry.
+
+use anyhow::{anyhow, Result};
+use mio_bridge::bridge::{Bridge, BridgeEnables, DispatchOutcome, DispatchStatus, Router};
+use mio_bridge::protocol::{self, Command, Source};
+use std::sync::{Arc, Mutex};
+
+/// A bridge that records what it was sent.
+struct Recorder {
+    name: &'static str,
+    prefix: &'static str,
+    sent: Arc<Mutex<Vec<String>>>,
+    fail: bool,
+}
+
+impl Recorder {
+    fn new(name: &'static str, prefix: &'static str) -> (Self, Arc<Mutex<Vec<String>>>) {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let bridge = Self {
+            name,
+            prefix,
+            sent: sent.clone(),
+            fail: false,
+        };
+        (bridge, sent)
+    }
+}
+
+impl Bridge for Recorder {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn handles(&self, cmd: &Command) -> bool {
+        matches!(cmd, Command::OscMessage { address, .. } if address.starts_with(self.prefix))
+    }
+
+    fn dispatch(&mut self, cmd: &Command, source: Source) -> Result<String> {
+        if self.fail {
+            return Err(anyhow!("REC ERROR: offline"));
+        }
+        self.sent.lock().unwrap().push(format!("{:?} from {}", cmd, source.as_str()));
+        Ok(format!("REC {}", self.name))
+    }
+
+    fn status(&self) -> String {
+        format!("{} sent", self.sent.lock().unwrap().len())
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        Err(anyhow!("REC {} shut down", self.name))
+    }
+}
+
+fn dispatch(router: &mut Router, line: &str) -> DispatchOutcome {
+    router.dispatch(&protocol::parse(line).unwrap(), Source::Serial)
+}
+
+#[test]
+fn test_registered_bridge_gets_its_commands() {
+    let mut router = Router::default();
+    let (lights, sent) = Recorder::new("lights", "/lights/");
+    router.register(Box::new(lights));
+
+    assert_eq!(dispatch(&mut router, "osc:/lights/1,255").to_string(), "REC lights");
+    assert_eq!(dispatch(&mut router, "osc:/other,1").to_string(), "OSC osc:/other,1 (disabled)");
+    assert_eq!(dispatch(&mut router, "key:tap,a").to_string(), "KEY key:tap,a (disabled)");
+    assert_eq!(sent.lock().unwrap().len(), 1);
+    assert!(sent.lock().unwrap()[0].ends_with("from serial"));
+}
+
+#[test]
+fn test_later_bridges_are_asked_first() {
+    let mut router = Router::default();
+    let (all, all_sent) = Recorder::new("all", "/");
+    let (lights, lights_sent) = Recorder::new("lights", "/lights/");
+    router.register(Box::new(all));
+    router.register(Box::new(lights));
+
+    assert_eq!(dispatch(&mut router, "osc:/lights/1").to_string(), "REC lights");
+    assert_eq!(dispatch(&mut router, "osc:/cue/go").to_string(), "REC all");
+    assert_eq!(lights_sent.lock().unwrap().len(), 1);
+    assert_eq!(all_sent.lock().unwrap().len(), 1);
+
+    let names: Vec<&str> = router.bridges().map(|b| b.name()).collect();
+    assert_eq!(names, ["lights", "all"]);
+    assert_eq!(router.bridges().next().unwrap().status(), "1 sent");
+}
+
+#[test]
+fn test_enables_apply_by_bridge_name() {
+    // A bridge named like a built-in one follows that bridge's enable
+    let mut router = Router::default();
+    let (osc, _) = Recorder::new("osc", "/");
+    router.register(Box::new(osc));
+    router.enabled = BridgeEnables {
+        osc: false,
+        ..Default::default()
+    };
+    assert_eq!(dispatch(&mut router, "osc:/cue/go").to_string(), "OSC osc:/cue/go (disabled)");
+
+    router.enabled.osc = true;
+    assert_eq!(dispatch(&mut router, "osc:/cue/go").to_string(), "REC osc");
+}
+
+#[test]
+fn test_errors_and_shutdown() {
+    let mut router = Router::default();
+    let (mut lights, _) = Recorder::new("lights", "/lights/");
+    lights.fail = true;
+    router.register(Box::new(lights));
+
+    assert_eq!(dispatch(&mut router, "osc:/lights/1").to_string(), "REC ERROR: offline");
+    assert_eq!(dispatch(&mut router, "profile:show").to_string(), "PROFILE show (disabled)");
+    assert!(router.tick().is_empty());
+    assert_eq!(router.shutdown(), vec!["REC lights shut down".to_string()]);
+}
+
+#[test]
+fn test_typed_access() {
+    let mut router = Router::default();
+    let (lights, _) = Recorder::new("lights", "/lights/");
+    router.register(Box::new(lights));
+
+    assert!(router.bridge::<Recorder>().is_some());
+    router.bridge_mut::<Recorder>().unwrap().fail = true;
+    assert_eq!(dispatch(&mut router, "osc:/lights/1").to_string(), "REC ERROR: offline");
+}
+
+// --- Outcomes ---
+
+#[test]
+fn test_dispatch_outcomes() {
+    let mut router = Router::default();
+    let (lights, _) = Recorder::new("lights", "/lights/");
+    router.register(Box::new(lights));
+
+    let ok = dispatch(&mut router, "osc:/lights/1");
+    assert_eq!((ok.bridge.as_str(), ok.status), ("lights", DispatchStatus::Ok));
+    assert_eq!(ok.detail, "REC lights");
+
+    let disabled = dispatch(&mut router, "midi:cc,1,2");
+    assert_eq!((disabled.bridge.as_str(), disabled.status), ("midi", DispatchStatus::Disabled));
+    assert_eq!(disabled.detail, "MIDI midi:cc,1,2,0");
+    assert!(disabled.latency.is_zero());
+
+    router.bridge_mut::<Recorder>().unwrap().fail = true;
+    let error = dispatch(&mut router, "osc:/lights/1");
+    assert_eq!((error.bridge.as_str(), error.status), ("lights", DispatchStatus::Error));
+
+    let outcomes = [ok, disabled, error];
+    assert_eq!(DispatchOutcome::worst(&outcomes), DispatchStatus::Error);
+    assert_eq!(DispatchOutcome::worst(&outcomes[..2]), DispatchStatus::Disabled);
+    assert_eq!(DispatchOutcome::worst(&[]), DispatchStatus::Ok);
+    assert_eq!(DispatchOutcome::join(&outcomes), "REC lights | MIDI midi:cc,1,2,0 (disabled) | REC ERROR: offline");
+}
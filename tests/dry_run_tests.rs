@@ -0,0 +1,149 @@
+//! Tests from protocol line to action, using the simulated bridges.
+
+use mio_bridge::bridge::simulated::{Action, MAX_ACTIONS};
+use mio_bridge::bridge::{DispatchOutcome, Router};
+use mio_bridge::config::{Config, Filter, MappingConfig, ProfileConfig, TriggerConfig};
+use mio_bridge::protocol::{Command, Source};
+use mio_bridge::routing::{self, Routing};
+use std::collections::BTreeMap;
+
+/// Dispatch `lines` from serial and return the log text of each.
+fn run(router: &mut Router, routing: &mut Routing, lines: &[&str]) -> Vec<String> {
+    lines
+        .iter()
+        .map(|line| {
+            let outcomes = routing::dispatch(router, routing, line, Source::Serial, |_| {}).expect("not a command");
+            DispatchOutcome::join(&outcomes)
+        })
+        .collect()
+}
+
+#[test]
+fn test_simulated_bridges_record_actions() {
+    let mut router = Router::simulated();
+    let mut routing = Routing::new(&Config::default());
+
+    let results = run(&mut router, &mut routing, &["key:tap,space", "mouse:click,left", "midi:cc,7,100"]);
+    assert_eq!(
+        results,
+        [
+            "KEY key:tap,space (simulated)",
+            "MOUSE mouse:click,left (simulated)",
+            "MIDI midi:cc,7,100,0 (simulated)",
+        ]
+    );
+    assert_eq!(
+        router.actions.actions()[0],
+        Action {
+            bridge: "keyboard",
+            command: Command::KeyTap("space".into()),
+            source: Source::Serial,
+        }
+    );
+    assert_eq!(router.actions.take().len(), 3);
+    assert!(router.actions.actions().is_empty());
+}
+
+#[test]
+fn test_action_log_keeps_latest() {
+    let mut router = Router::simulated();
+    for i in 0..MAX_ACTIONS + 5 {
+        router.dispatch(&Command::MouseMove { x: i as i32, y: 0 }, Source::Serial);
+    }
+    let lines = router.actions.lines();
+    assert_eq!(lines.len(), MAX_ACTIONS);
+    assert_eq!(lines[0], "mouse:move,5,0");
+    assert_eq!(lines[MAX_ACTIONS - 1], format!("mouse:move,{},0", MAX_ACTIONS + 4));
+}
+
+#[test]
+fn test_mapped_line_to_actions() {
+    let config = Config {
+        mappings: vec![MappingConfig {
+            id: Some("btn".into()),
+            min: Some(1.0),
+            emit: vec!["key:tap,enter".into(), "midi:note_on,60,{value}".into()],
+            ..Default::default()
+        }],
+        keys: BTreeMap::from([("enter".to_string(), "space".to_string())]),
+        ..Default::default()
+    };
+    let mut router = Router::simulated();
+    let mut routing = Routing::new(&config);
+
+    run(&mut router, &mut routing, &["ws:btn,0", "ws:btn,100"]);
+    assert_eq!(router.actions.lines(), ["key:tap,space", "midi:note_on,60,100,0"]);
+}
+
+#[test]
+fn test_trigger_holds_and_releases_key() {
+    let config = Config {
+        triggers: vec![TriggerConfig {
+            id: Some("dist".into()),
+            below: Some(30.0),
+            hold: Some("w".into()),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let mut router = Router::simulated();
+    let mut routing = Routing::new(&config);
+
+    run(&mut router, &mut routing, &["ws:dist,50", "ws:dist,20", "ws:dist,25", "ws:dist,40"]);
+    assert_eq!(router.actions.lines(), ["key:down,w", "key:down,w", "key:up,w"]);
+}
+
+#[test]
+fn test_trigger_hold_survives_filtered_values() {
+    let config = Config {
+        filters: BTreeMap::from([("dist".to_string(), vec![Filter::Change])]),
+        triggers: vec![TriggerConfig {
+            id: Some("dist".into()),
+            below: Some(30.0),
+            hold: Some("w".into()),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let mut router = Router::simulated();
+    let mut routing = Routing::new(&config);
+
+    let results = run(&mut router, &mut routing, &["ws:dist,20", "ws:dist,20"]);
+    assert_eq!(results[1], "FILTERED");
+
+    // Every tick refreshes the held key for the watchdog without pressing it again
+    let mut refreshed = Vec::new();
+    routing::flush(&mut router, &mut routing, |cmd| refreshed.push(cmd.clone()));
+    assert_eq!(refreshed, [Command::KeyDown("w".into())]);
+    assert_eq!(router.actions.lines(), ["key:down,w"]);
+
+    run(&mut router, &mut routing, &["ws:dist,40"]);
+    refreshed.clear();
+    routing::flush(&mut router, &mut routing, |cmd| refreshed.push(cmd.clone()));
+    assert!(refreshed.is_empty());
+    assert_eq!(router.actions.lines(), ["key:down,w", "key:up,w"]);
+}
+
+#[test]
+fn test_profile_disables_simulated_keyboard() {
+    let config = Config {
+        profiles: BTreeMap::from([(
+            "quiet".to_string(),
+            ProfileConfig {
+                keyboard: Some(false),
+                ..Default::default()
+            },
+        )]),
+        ..Default::default()
+    };
+    let mut router = Router::simulated();
+    let mut routing = Routing::new(&config);
+
+    let results = run(&mut router, &mut routing, &["key:down,a", "profile:quiet", "key:tap,b"]);
+    assert_eq!(results[2], "KEY key:tap,b (disabled)");
+    assert_eq!(router.actions.lines(), ["key:down,a"]);
+
+    // Held keys are still released on the disabled bridge
+    router.release_all_keys(&["a".to_string()]);
+    assert_eq!(router.actions.lines(), ["key:down,a", "key:up,a"]);
+}
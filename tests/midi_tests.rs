@@ -0,0 +1,75 @@
+//! Tests for the Standard MIDI File recorder.
+
+use mio_bridge::bridge::midi_recorder::{encode_smf, MidiRecorder};
+use std::time::Duration;
+
+const HEADER: [u8; 14] = [
+    b'M', b'T', b'h', b'd', 0, 0, 0, 6, // chunk length
+    0, 0, // format 0
+    0, 1, // one track
+    0x01, 0xE0, // 480 ticks per quarter
+];
+
+/// Tempo meta event at t=0: 500000 µs per quarter.
+const TEMPO: [u8; 7] = [0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20];
+
+const END_OF_TRACK: [u8; 4] = [0x00, 0xFF, 0x2F, 0x00];
+
+fn smf(events: &[u8]) -> Vec<u8> {
+    let mut track = TEMPO.to_vec();
+    track.extend_from_slice(events);
+    track.extend_from_slice(&END_OF_TRACK);
+    let mut out = HEADER.to_vec();
+    out.extend_from_slice(b"MTrk");
+    out.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    out.extend_from_slice(&track);
+    out
+}
+
+#[test]
+fn test_empty_recording() {
+    assert_eq!(encode_smf(&[]), smf(&[]));
+}
+
+#[test]
+fn test_events_and_deltas() {
+    let events = vec![
+        (Duration::ZERO, vec![0x90, 60, 100]),
+        // 1 s at 120 BPM is 960 ticks: a two-byte delta
+        (Duration::from_secs(1), vec![0x80, 60, 0]),
+        // Same time: delta 0; SysEx gets its length after F0
+        (Duration::from_secs(1), vec![0xF0, 0x7E, 0xF7]),
+    ];
+    assert_eq!(
+        encode_smf(&events),
+        smf(&[
+            0x00, 0x90, 0x3C, 0x64, //
+            0x87, 0x40, 0x80, 0x3C, 0x00, //
+            0x00, 0xF0, 0x02, 0x7E, 0xF7,
+        ])
+    );
+}
+
+#[test]
+fn test_three_byte_delta() {
+    // 100 s is 96000 ticks
+    let events = vec![(Duration::from_secs(100), vec![0xB0, 7, 127])];
+    assert_eq!(encode_smf(&events), smf(&[0x85, 0xEE, 0x00, 0xB0, 0x07, 0x7F]));
+}
+
+#[test]
+fn test_recorder_writes_file() {
+    let path = std::env::temp_dir().join(format!("mio-test-{}.mid", std::process::id()));
+    let mut recorder = MidiRecorder::new(path.clone());
+    recorder.record(&[0x90, 60, 100]);
+    recorder.record(&[]);
+    let (written, count) = recorder.finish().unwrap();
+
+    let data = std::fs::read(&written).unwrap();
+    std::fs::remove_file(&written).unwrap();
+    assert_eq!(written, path);
+    assert_eq!(count, 2);
+    assert_eq!(&data[..14], &HEADER);
+    // Empty messages are skipped
+    assert!(data.ends_with(&[0x90, 0x3C, 0x64, 0x00, 0xFF, 0x2F, 0x00]));
+}
@@ -0,0 +1,362 @@
+//! Tests for the OSC bridge: argument typing, sending and SLIP framing.
+
+use mio_bridge::bridge::osc::OscBridge;
+use mio_bridge::bridge::osc_tcp::{self, LinkState, SlipDecoder, MAX_FRAME_LEN};
+use mio_bridge::bridge::osc_types::{self, OscTypeSchema};
+use mio_bridge::config::{OscConfig, OscDestinationConfig, OscSendMode, OscTransport};
+use rosc::{OscMidiMessage, OscPacket, OscTime, OscType};
+use std::collections::BTreeMap;
+use std::net::{TcpListener, UdpSocket};
+use std::time::Duration;
+
+fn arg(raw: &str) -> OscType {
+    osc_types::parse_arg(raw, None).unwrap()
+}
+
+// --- Type tags ---
+
+#[test]
+fn test_numeric_tags() {
+    assert_eq!(arg("i:42"), OscType::Int(42));
+    assert_eq!(arg("h:9000000000"), OscType::Long(9_000_000_000));
+    assert_eq!(arg("f:0.5"), OscType::Float(0.5));
+    assert_eq!(arg("d:0.25"), OscType::Double(0.25));
+}
+
+#[test]
+fn test_text_tags() {
+    assert_eq!(arg("s:42"), OscType::String("42".into()));
+    assert_eq!(arg("s: padded "), OscType::String(" padded ".into()));
+    assert_eq!(arg("c:x"), OscType::Char('x'));
+}
+
+#[test]
+fn test_blob_tag() {
+    assert_eq!(arg("b:AQID"), OscType::Blob(vec![1, 2, 3]));
+}
+
+#[test]
+fn test_midi_tag() {
+    assert_eq!(
+        arg("m:144:60:127"),
+        OscType::Midi(OscMidiMessage {
+            port: 0,
+            status: 144,
+            data1: 60,
+            data2: 127
+        })
+    );
+    assert_eq!(
+        arg("m:1:176:7:100"),
+        OscType::Midi(OscMidiMessage {
+            port: 1,
+            status: 176,
+            data1: 7,
+            data2: 100
+        })
+    );
+}
+
+#[test]
+fn test_time_tag() {
+    // The Unix epoch is 2208988800 seconds into the NTP era
+    assert_eq!(
+        arg("t:0"),
+        OscType::Time(OscTime {
+            seconds: 2_208_988_800,
+            fractional: 0
+        })
+    );
+    assert!(matches!(arg("t:now"), OscType::Time(_)));
+    assert!(matches!(arg("t:+0.5"), OscType::Time(_)));
+}
+
+#[test]
+fn test_bare_tags() {
+    assert_eq!(arg("T"), OscType::Bool(true));
+    assert_eq!(arg("F"), OscType::Bool(false));
+    assert_eq!(arg("N"), OscType::Nil);
+    assert_eq!(arg("I"), OscType::Inf);
+}
+
+#[test]
+fn test_untagged_guess() {
+    assert_eq!(arg("0.5"), OscType::Float(0.5));
+    assert_eq!(arg("42"), OscType::Float(42.0));
+    assert_eq!(arg("hello"), OscType::String("hello".into()));
+    // Not a known tag, so the colon is part of the string
+    assert_eq!(arg("x:1"), OscType::String("x:1".into()));
+}
+
+// --- Bad values ---
+
+#[test]
+fn test_bad_value_under_tag() {
+    for raw in ["i:abc", "i:1.5", "h:x", "f:x", "d:x", "c:xy", "b:!!", "m:144:60", "m:300:1:1", "t:soon"] {
+        assert!(osc_types::parse_arg(raw, None).is_err(), "{} should not parse", raw);
+    }
+}
+
+#[test]
+fn test_bad_value_names_the_argument() {
+    let args = vec!["i:1".to_string(), "i:abc".to_string()];
+    let err = osc_types::parse_args(&args, None).unwrap_err();
+    assert_eq!(format!("{:#}", err), "OSC argument 2 (\"i:abc\"): invalid int");
+}
+
+// --- Schema ---
+
+#[test]
+fn test_schema_string_takes_bare_tags_literally() {
+    let args = vec!["T".to_string(), "F".to_string(), "N".to_string()];
+    assert_eq!(
+        osc_types::parse_args(&args, Some("sss")).unwrap(),
+        [OscType::String("T".into()), OscType::String("F".into()), OscType::String("N".into())]
+    );
+    // Under a bool or nil type the bare tags keep their meaning
+    assert_eq!(osc_types::parse_args(&args[..2], Some("TT")).unwrap(), [OscType::Bool(true), OscType::Bool(false)]);
+    assert_eq!(osc_types::parse_arg("N", Some('N')).unwrap(), OscType::Nil);
+}
+
+#[test]
+fn test_schema_rejects_unknown_tags() {
+    let types = BTreeMap::from([("/cue".to_string(), "iq".to_string())]);
+    let err = OscTypeSchema::new(&types).unwrap_err();
+    assert_eq!(err.to_string(), "osc.types: unknown OSC type tag 'q' for /cue");
+}
+
+#[test]
+fn test_schema_hints() {
+    let args = vec!["1".to_string(), "2".to_string(), "on".to_string(), "3".to_string()];
+    assert_eq!(
+        osc_types::parse_args(&args, Some("ihT")).unwrap(),
+        [OscType::Int(1), OscType::Long(2), OscType::Bool(true), OscType::Float(3.0)]
+    );
+}
+
+#[test]
+fn test_explicit_tag_beats_schema() {
+    let args = vec!["f:0.5".to_string()];
+    assert_eq!(osc_types::parse_args(&args, Some("i")).unwrap(), [OscType::Float(0.5)]);
+    assert!(osc_types::parse_args(&["x".to_string()], Some("i")).is_err());
+}
+
+#[test]
+fn test_schema_lookup() {
+    let schema = OscTypeSchema::new(&BTreeMap::from([
+        ("/layer1/clip".to_string(), "ii".to_string()),
+        ("/layer*".to_string(), "f".to_string()),
+        ("/layer1/*".to_string(), "s".to_string()),
+    ]))
+    .unwrap();
+    assert_eq!(schema.lookup("/layer1/clip"), Some("ii"));
+    assert_eq!(schema.lookup("/layer1/opacity"), Some("s"));
+    assert_eq!(schema.lookup("/layer2/opacity"), Some("f"));
+    assert_eq!(schema.lookup("/composition"), None);
+}
+
+// --- Sending ---
+
+/// A local UDP socket standing in for an OSC destination.
+fn receiver() -> (UdpSocket, u16) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+    let port = socket.local_addr().unwrap().port();
+    (socket, port)
+}
+
+fn destination(name: &str, port: u16, prefixes: &[&str]) -> OscDestinationConfig {
+    OscDestinationConfig {
+        name: name.into(),
+        address: "127.0.0.1".into(),
+        port,
+        mode: OscSendMode::Unicast,
+        transport: OscTransport::Udp,
+        prefixes: prefixes.iter().map(|p| p.to_string()).collect(),
+    }
+}
+
+fn bridge(destinations: Vec<OscDestinationConfig>, bundle_window_ms: u64) -> OscBridge {
+    OscBridge::new(&OscConfig {
+        local_address: "127.0.0.1".into(),
+        local_port: 0,
+        destinations,
+        bundle_window_ms,
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+/// The next packet on `socket`, or `None` if nothing arrives in time.
+fn recv(socket: &UdpSocket) -> Option<OscPacket> {
+    let mut buf = [0u8; 1536];
+    let len = socket.recv(&mut buf).ok()?;
+    Some(rosc::decoder::decode_udp(&buf[..len]).unwrap().1)
+}
+
+fn addresses(packet: OscPacket) -> Vec<String> {
+    match packet {
+        OscPacket::Message(msg) => vec![msg.addr],
+        OscPacket::Bundle(bundle) => bundle.content.into_iter().flat_map(addresses).collect(),
+    }
+}
+
+#[test]
+fn test_send_to_matching_destinations() {
+    let (lights, lights_port) = receiver();
+    let (all, all_port) = receiver();
+    let destinations = vec![destination("lights", lights_port, &["/lights/"]), destination("all", all_port, &[])];
+    let mut osc = bridge(destinations, 0);
+
+    osc.send("/lights/1", &["i:255".into()]).unwrap();
+    osc.send("/cue/go", &[]).unwrap();
+
+    let Some(OscPacket::Message(msg)) = recv(&lights) else { panic!("expected a message") };
+    assert_eq!((msg.addr.as_str(), msg.args), ("/lights/1", vec![OscType::Int(255)]));
+    assert!(recv(&lights).is_none());
+    assert_eq!(addresses(recv(&all).unwrap()), ["/lights/1"]);
+    assert_eq!(addresses(recv(&all).unwrap()), ["/cue/go"]);
+}
+
+#[test]
+fn test_send_without_destination_fails() {
+    let (_lights, lights_port) = receiver();
+    let mut osc = bridge(vec![destination("lights", lights_port, &["/lights/"])], 0);
+    assert!(osc.send("/cue/go", &[]).is_err());
+}
+
+#[test]
+fn test_bundle_window_groups_messages() {
+    let (lights, lights_port) = receiver();
+    let (all, all_port) = receiver();
+    let destinations = vec![destination("lights", lights_port, &["/lights/"]), destination("all", all_port, &[])];
+    let mut osc = bridge(destinations, 50);
+
+    osc.send("/lights/1", &["1".into()]).unwrap();
+    osc.send("/cue/go", &[]).unwrap();
+    osc.send("/lights/2", &["2".into()]).unwrap();
+    osc.flush_due().unwrap();
+    assert!(recv(&all).is_none(), "sent before the window elapsed");
+
+    std::thread::sleep(Duration::from_millis(60));
+    osc.flush_due().unwrap();
+    let Some(OscPacket::Bundle(bundle)) = recv(&all) else { panic!("expected a bundle") };
+    assert_eq!(bundle.timetag, OscTime::from((0, 1)));
+    assert_eq!(addresses(OscPacket::Bundle(bundle)), ["/lights/1", "/cue/go", "/lights/2"]);
+    assert_eq!(addresses(recv(&lights).unwrap()), ["/lights/1", "/lights/2"]);
+
+    // The window starts over with the next message
+    osc.flush_due().unwrap();
+    assert!(recv(&all).is_none());
+}
+
+#[test]
+fn test_drop_flushes_queued_bundle() {
+    let (all, all_port) = receiver();
+    let mut osc = bridge(vec![destination("all", all_port, &[])], 60_000);
+
+    osc.send("/a", &[]).unwrap();
+    osc.send("/b", &[]).unwrap();
+    drop(osc);
+    assert_eq!(addresses(recv(&all).unwrap()), ["/a", "/b"]);
+    assert!(recv(&all).is_none());
+}
+
+#[test]
+fn test_set_destinations_keeps_unchanged_tcp_links() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let tcp = OscDestinationConfig {
+        transport: OscTransport::Tcp,
+        ..destination("show", port, &[])
+    };
+    let mut osc = bridge(vec![tcp.clone()], 0);
+    let (_conn, _) = listener.accept().unwrap();
+    wait_for_link(&osc, LinkState::Connected);
+
+    // Same address and transport under a new name: no reconnect
+    let renamed = OscDestinationConfig {
+        name: "rehearsal".into(),
+        ..tcp.clone()
+    };
+    osc.set_destinations(&[renamed]).unwrap();
+    let links = osc.links();
+    assert_eq!((links[0].name.as_str(), &links[0].state), ("rehearsal", &LinkState::Connected));
+    listener.set_nonblocking(true).unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(listener.accept().is_err(), "link reconnected");
+
+    // A different transport gets a new link
+    let (_udp, udp_port) = receiver();
+    osc.set_destinations(&[destination("show", udp_port, &[])]).unwrap();
+    assert_eq!(osc.links()[0].transport, OscTransport::Udp);
+}
+
+fn wait_for_link(osc: &OscBridge, state: LinkState) {
+    for _ in 0..50 {
+        if osc.links()[0].state == state {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    panic!("link never reached {:?}", state);
+}
+
+// --- SLIP framing ---
+
+#[test]
+fn test_slip_escaping() {
+    assert_eq!(osc_tcp::slip_encode(b"ab"), [0xC0, b'a', b'b', 0xC0]);
+    assert_eq!(osc_tcp::slip_encode(&[0xC0, 1, 0xDB]), [0xC0, 0xDB, 0xDC, 1, 0xDB, 0xDD, 0xC0]);
+    assert_eq!(SlipDecoder::default().push(&[0xC0, 0xDB, 0xDC, 1, 0xDB, 0xDD, 0xC0]), [vec![0xC0, 1, 0xDB]]);
+}
+
+#[test]
+fn test_slip_round_trip() {
+    let packet = rosc::encoder::encode(&OscPacket::Message(rosc::OscMessage {
+        addr: "/blob".into(),
+        args: vec![OscType::Blob(vec![0xC0, 0xDB, 0xDC, 0xDD, 0])],
+    }))
+    .unwrap();
+    let mut stream = osc_tcp::slip_encode(&packet);
+    stream.extend(osc_tcp::slip_encode(b"second"));
+    assert_eq!(SlipDecoder::default().push(&stream), [packet, b"second".to_vec()]);
+}
+
+#[test]
+fn test_slip_frames_split_across_reads() {
+    let stream = [osc_tcp::slip_encode(&[1, 0xC0, 2]), osc_tcp::slip_encode(&[3])].concat();
+    // Split everywhere, including between an ESC and the byte it escapes
+    for split in 0..=stream.len() {
+        let mut decoder = SlipDecoder::default();
+        let mut frames = decoder.push(&stream[..split]);
+        frames.extend(decoder.push(&stream[split..]));
+        assert_eq!(frames, [vec![1, 0xC0, 2], vec![3]], "split at {}", split);
+    }
+
+    let mut decoder = SlipDecoder::default();
+    let frames: Vec<Vec<u8>> = stream.iter().flat_map(|b| decoder.push(&[*b])).collect();
+    assert_eq!(frames, [vec![1, 0xC0, 2], vec![3]]);
+}
+
+#[test]
+fn test_slip_skips_empty_frames() {
+    assert_eq!(SlipDecoder::default().push(&[0xC0, 0xC0, 0xC0, 7, 0xC0]), [vec![7]]);
+    assert!(SlipDecoder::default().push(&[7, 8]).is_empty());
+}
+
+#[test]
+fn test_slip_drops_oversized_frames() {
+    let mut decoder = SlipDecoder::default();
+    let chunk = vec![7u8; 4096];
+    for _ in 0..(MAX_FRAME_LEN / chunk.len() + 1) {
+        assert!(decoder.push(&chunk).is_empty());
+    }
+    // The oversized frame ends at the next END; the one after it is kept
+    assert!(decoder.push(&chunk).is_empty());
+    assert_eq!(decoder.push(&[0xC0, 1, 2, 0xC0]), [vec![1, 2]]);
+
+    // A frame of exactly the limit still gets through
+    let full = vec![7u8; MAX_FRAME_LEN];
+    assert_eq!(SlipDecoder::default().push(&osc_tcp::slip_encode(&full)), [full]);
+}
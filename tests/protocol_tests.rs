@@ -210,6 +210,15 @@ fn test_osc_multiple_args() {
     );
 }
 
+// --- Profiles ---
+
+#[test]
+fn test_profile() {
+    assert_eq!(protocol::parse("profile:show-a"), Some(Command::Profile("show-a".into())));
+    assert_eq!(protocol::parse("profile:"), None);
+    assert_eq!(protocol::parse("profile:a,b"), None);
+}
+
 // --- Edge cases ---
 
 #[test]
@@ -0,0 +1,433 @@
+//! Tests for the routing layer between the parser and the router.
+
+use mio_bridge::config::{Config, MappingConfig, ProfileConfig, Transform, TriggerConfig};
+use mio_bridge::protocol::{self, Command, Source};
+use mio_bridge::config::Filter;
+use mio_bridge::routing::filter::{FilterChain, FilterResult};
+use mio_bridge::routing::profile::Profiles;
+use mio_bridge::routing::trigger::{Edge, Trigger};
+use mio_bridge::routing::{template, transform, Routed, Routing};
+use std::time::{Duration, Instant};
+
+fn route(routing: &mut Routing, line: &str, source: Source) -> Routed {
+    try_route(routing, line, source).expect("line was filtered")
+}
+
+fn try_route(routing: &mut Routing, line: &str, source: Source) -> Option<Routed> {
+    routing.apply(line, protocol::parse(line).unwrap(), source)
+}
+
+fn with_mappings(mappings: Vec<MappingConfig>) -> Routing {
+    Routing::new(&Config {
+        mappings,
+        ..Default::default()
+    })
+}
+
+// --- Mappings ---
+
+#[test]
+fn test_mapping_emits_commands() {
+    let mut mappings = with_mappings(vec![MappingConfig {
+        id: Some("button1".into()),
+        min: Some(1.0),
+        emit: vec!["key:tap,space".into(), "osc:/cue/go".into()],
+        ..Default::default()
+    }]);
+
+    assert_eq!(
+        route(&mut mappings, "ws:button1,1", Source::Serial),
+        vec![
+            Ok(Command::KeyTap("space".into())),
+            Ok(Command::OscMessage { address: "/cue/go".into(), args: vec![] }),
+        ]
+    );
+    // Below `min` and other ids pass through unchanged
+    assert_eq!(
+        route(&mut mappings, "ws:button1,0", Source::Serial),
+        vec![Ok(Command::WsBroadcast { id: "button1".into(), value: "0".into() })]
+    );
+    assert_eq!(route(&mut mappings, "key:tap,a", Source::Serial), vec![Ok(Command::KeyTap("a".into()))]);
+}
+
+#[test]
+fn test_mapping_conditions() {
+    let mut mappings = with_mappings(vec![
+        MappingConfig {
+            address: Some("/fader*".into()),
+            max: Some(0.5),
+            source: Some("osc".into()),
+            emit: vec!["midi:cc,7,{0}".into()],
+            ..Default::default()
+        },
+        MappingConfig {
+            prefix: Some("midi:cc,".into()),
+            emit: vec!["ws:cc{0},{1}".into()],
+            passthrough: true,
+            ..Default::default()
+        },
+    ]);
+
+    assert_eq!(
+        route(&mut mappings, "osc:/fader1,0", Source::Osc),
+        vec![Ok(Command::MidiCc { controller: 7, value: 0, channel: 0 })]
+    );
+    // Wrong source or out of range: no rule matches
+    assert_eq!(route(&mut mappings, "osc:/fader1,0", Source::Serial).len(), 1);
+    assert_eq!(route(&mut mappings, "osc:/fader1,0.9", Source::Osc).len(), 1);
+
+    assert_eq!(
+        route(&mut mappings, "midi:cc,1,64", Source::Serial),
+        vec![
+            Ok(Command::MidiCc { controller: 1, value: 64, channel: 0 }),
+            Ok(Command::WsBroadcast { id: "cc1".into(), value: "64".into() }),
+        ]
+    );
+}
+
+#[test]
+fn test_mapping_unparsable_emit() {
+    let mut mappings = with_mappings(vec![MappingConfig {
+        id: Some("x".into()),
+        emit: vec!["nope:{value}".into()],
+        ..Default::default()
+    }]);
+    assert_eq!(route(&mut mappings, "ws:x,5", Source::WebSocket), vec![Err("nope:5".into())]);
+}
+
+// --- Templates ---
+
+#[test]
+fn test_template_placeholders() {
+    let args = vec!["1".to_string(), "2".to_string()];
+    let named = |name: &str| (name == "id").then(|| "pot".to_string());
+    assert_eq!(template::render("ws:{id},{args}", &args, named), "ws:pot,1,2");
+    assert_eq!(template::render("{1}{0}{5}", &args, named), "21");
+    assert_eq!(template::render("{other} {id", &args, named), "{other} {id");
+}
+
+// --- Transforms ---
+
+#[test]
+fn test_transform_stages() {
+    let map = Transform::Map { from: [0.0, 1023.0], to: [0.0, 127.0] };
+    assert_eq!(map.apply(1023.0), 127.0);
+    assert_eq!(Transform::Clamp([0.0, 1.0]).apply(1.5), 1.0);
+    assert_eq!(Transform::Invert([0.0, 1023.0]).apply(23.0), 1000.0);
+    assert_eq!(Transform::Quantize(0.25).apply(0.3), 0.25);
+    assert_eq!(Transform::Exp(3.0).apply(1.0), 1.0);
+    assert!(Transform::Exp(3.0).apply(0.5) < 0.5);
+    assert!(Transform::Log(9.0).apply(0.5) > 0.5);
+
+    let lut = Transform::Lut(vec![[0.0, 0.0], [10.0, 100.0], [20.0, 120.0]]);
+    assert_eq!(lut.apply(5.0), 50.0);
+    assert_eq!(lut.apply(15.0), 110.0);
+    assert_eq!(lut.apply(-1.0), 0.0);
+    assert_eq!(lut.apply(99.0), 120.0);
+
+    assert_eq!(transform::format_number(63.0), "63");
+    assert_eq!(transform::format_number(0.5), "0.5");
+    assert_eq!(transform::apply_to_arg(&[Transform::Quantize(1.0)], "left"), "left");
+}
+
+#[test]
+fn test_transform_per_id_and_per_rule() {
+    let mut config = Config::default();
+    config.transforms.insert(
+        "pot".into(),
+        vec![Transform::Map { from: [0.0, 1023.0], to: [0.0, 1.0] }],
+    );
+    config.mappings.push(MappingConfig {
+        id: Some("pot".into()),
+        min: Some(0.5),
+        transform: vec![Transform::Map { from: [0.0, 1.0], to: [0.0, 127.0] }, Transform::Quantize(1.0)],
+        emit: vec!["midi:cc,7,{value}".into()],
+        passthrough: true,
+        ..Default::default()
+    });
+    let mut routing = Routing::new(&config);
+
+    // Below 0.5 after the per-id transform: only the scaled broadcast
+    assert_eq!(
+        route(&mut routing, "ws:pot,0", Source::Serial),
+        vec![Ok(Command::WsBroadcast { id: "pot".into(), value: "0".into() })]
+    );
+    assert_eq!(
+        route(&mut routing, "ws:pot,1023", Source::Serial),
+        vec![
+            Ok(Command::WsBroadcast { id: "pot".into(), value: "1".into() }),
+            Ok(Command::MidiCc { controller: 7, value: 127, channel: 0 }),
+        ]
+    );
+}
+
+// --- Filters ---
+
+#[test]
+fn test_filter_smoothing() {
+    let now = Instant::now();
+    let mut ema = FilterChain::new(&[Filter::Ema(0.5)]);
+    assert_eq!(ema.process(vec![0.0], now), FilterResult::Pass(vec![0.0]));
+    assert_eq!(ema.process(vec![10.0], now), FilterResult::Pass(vec![5.0]));
+
+    let mut median = FilterChain::new(&[Filter::Median(3)]);
+    median.process(vec![1.0, 10.0], now);
+    median.process(vec![100.0, 20.0], now);
+    assert_eq!(median.process(vec![2.0, 30.0], now), FilterResult::Pass(vec![2.0, 20.0]));
+}
+
+#[test]
+fn test_filter_deadband_and_change() {
+    let now = Instant::now();
+    let mut deadband = FilterChain::new(&[Filter::Deadband(2.0)]);
+    assert_eq!(deadband.process(vec![100.0], now), FilterResult::Pass(vec![100.0]));
+    assert_eq!(deadband.process(vec![101.5], now), FilterResult::Drop);
+    assert_eq!(deadband.process(vec![98.0], now), FilterResult::Pass(vec![98.0]));
+
+    let mut change = FilterChain::new(&[Filter::Change]);
+    assert_eq!(change.process(vec![1.0], now), FilterResult::Pass(vec![1.0]));
+    assert_eq!(change.process(vec![1.0], now), FilterResult::Drop);
+    change.reset();
+    assert_eq!(change.process(vec![1.0], now), FilterResult::Pass(vec![1.0]));
+}
+
+#[test]
+fn test_filter_throttle_trailing_edge() {
+    let start = Instant::now();
+    let mut throttle = FilterChain::new(&[Filter::ThrottleMs(50), Filter::Change]);
+    assert_eq!(throttle.process(vec![1.0], start), FilterResult::Pass(vec![1.0]));
+    assert_eq!(throttle.process(vec![2.0], start + Duration::from_millis(10)), FilterResult::Hold);
+    assert_eq!(throttle.process(vec![3.0], start + Duration::from_millis(20)), FilterResult::Hold);
+    assert_eq!(throttle.flush(start + Duration::from_millis(40)), None);
+    // The latest held value is sent when the interval ends
+    assert_eq!(throttle.flush(start + Duration::from_millis(50)), Some(FilterResult::Pass(vec![3.0])));
+    assert_eq!(throttle.flush(start + Duration::from_millis(200)), None);
+}
+
+#[test]
+fn test_filters_in_routing() {
+    let mut config = Config::default();
+    config.filters.insert("pot".into(), vec![Filter::Change]);
+    let mut routing = Routing::new(&config);
+
+    assert_eq!(route(&mut routing, "ws:pot,5", Source::Serial).len(), 1);
+    assert_eq!(try_route(&mut routing, "ws:pot,5", Source::Serial), None);
+    assert_eq!(route(&mut routing, "ws:other,5", Source::Serial).len(), 1);
+    routing.reset();
+    assert_eq!(route(&mut routing, "ws:pot,5", Source::Serial).len(), 1);
+}
+
+// --- Triggers ---
+
+#[test]
+fn test_trigger_hysteresis_and_interval() {
+    let rule = TriggerConfig {
+        below: Some(30.0),
+        hysteresis: 5.0,
+        min_interval_ms: 500,
+        ..Default::default()
+    };
+    let start = Instant::now();
+    let mut trigger = Trigger::default();
+    assert_eq!(trigger.update(&rule, 50.0, start), None);
+    assert_eq!(trigger.update(&rule, 29.0, start), Some(Edge::Enter));
+    // Jitter around the threshold doesn't release
+    assert_eq!(trigger.update(&rule, 31.0, start), Some(Edge::Stay));
+    assert_eq!(trigger.update(&rule, 35.0, start), Some(Edge::Leave));
+    // Too soon to fire again, until the interval is over
+    assert_eq!(trigger.update(&rule, 20.0, start + Duration::from_millis(100)), None);
+    assert_eq!(trigger.update(&rule, 20.0, start + Duration::from_millis(500)), Some(Edge::Enter));
+
+    // Without a threshold nothing fires
+    assert_eq!(Trigger::default().update(&TriggerConfig::default(), 0.0, start), None);
+}
+
+#[test]
+fn test_trigger_repeat() {
+    let rule = TriggerConfig {
+        above: Some(700.0),
+        repeat_ms: 100,
+        ..Default::default()
+    };
+    let start = Instant::now();
+    let mut trigger = Trigger::default();
+    assert_eq!(trigger.update(&rule, 800.0, start), Some(Edge::Enter));
+    assert!(!trigger.repeat_due(&rule, start + Duration::from_millis(50)));
+    assert!(trigger.repeat_due(&rule, start + Duration::from_millis(100)));
+    assert!(!trigger.repeat_due(&rule, start + Duration::from_millis(150)));
+    assert_eq!(trigger.update(&rule, 600.0, start), Some(Edge::Leave));
+    assert!(!trigger.repeat_due(&rule, start + Duration::from_secs(1)));
+}
+
+#[test]
+fn test_triggers_in_routing() {
+    let mut routing = Routing::new(&Config {
+        triggers: vec![TriggerConfig {
+            id: Some("dist".into()),
+            below: Some(30.0),
+            emit: vec!["osc:/near,{value}".into()],
+            release: vec!["osc:/far".into()],
+            hold: Some("space".into()),
+            ..Default::default()
+        }],
+        ..Default::default()
+    });
+    let dist = |value: &str| Ok(Command::WsBroadcast { id: "dist".into(), value: value.into() });
+
+    assert_eq!(route(&mut routing, "ws:dist,40", Source::Serial), vec![dist("40")]);
+    assert_eq!(
+        route(&mut routing, "ws:dist,20", Source::Serial),
+        vec![
+            dist("20"),
+            Ok(Command::KeyDown("space".into())),
+            Ok(Command::OscMessage { address: "/near".into(), args: vec!["20".into()] }),
+        ]
+    );
+    // The held key is pressed again with every value while active
+    assert_eq!(
+        route(&mut routing, "ws:dist,25", Source::Serial),
+        vec![dist("25"), Ok(Command::KeyDown("space".into()))]
+    );
+    assert_eq!(
+        route(&mut routing, "ws:dist,45", Source::Serial),
+        vec![
+            dist("45"),
+            Ok(Command::OscMessage { address: "/far".into(), args: vec![] }),
+            Ok(Command::KeyUp("space".into())),
+        ]
+    );
+    // Other ids are not watched
+    assert_eq!(route(&mut routing, "ws:other,0", Source::Serial).len(), 1);
+}
+
+// --- Script ---
+
+fn with_script(name: &str, source: &str) -> Routing {
+    let path = std::env::temp_dir().join(format!("mio-{}-{}.rhai", name, std::process::id()));
+    std::fs::write(&path, source).unwrap();
+    let mut config = Config::default();
+    config.script.path = Some(path.to_string_lossy().into_owned());
+    Routing::new(&config)
+}
+
+#[test]
+fn test_script_hooks() {
+    let mut routing = with_script(
+        "hooks",
+        r#"
+        fn init() { this.presses = 0; }
+        fn on_line(source, line) {
+            if line.starts_with("D:") { return `ws:dist,${line.sub_string(2)}`; }
+            if line == "noise" { return false; }
+            if line == "key:tap,a" {
+                this.presses += 1;
+                if this.presses == 2 { emit("key:type,combo"); }
+            }
+        }
+        fn on_command(cmd) {
+            if cmd == "key:tap,b" { return "key:tap,c"; }
+        }
+        "#,
+    );
+    assert!(routing.take_log()[0].starts_with("SCRIPT: loaded"));
+
+    assert_eq!(routing.script_line("D:42", Source::Serial), (Some("ws:dist,42".into()), vec![]));
+    assert_eq!(routing.script_line("noise", Source::Serial), (None, vec![]));
+    // State in `this` is kept between calls
+    assert_eq!(routing.script_line("key:tap,a", Source::Serial).1, Vec::<String>::new());
+    assert_eq!(routing.script_line("key:tap,a", Source::Serial).1, vec!["key:type,combo".to_string()]);
+
+    assert_eq!(
+        routing.script_command(Command::KeyTap("b".into())),
+        (Some(Ok(Command::KeyTap("c".into()))), vec![])
+    );
+    assert_eq!(
+        routing.script_command(Command::MouseClick("left".into())),
+        (Some(Ok(Command::MouseClick("left".into()))), vec![])
+    );
+}
+
+#[test]
+fn test_script_timers() {
+    let mut routing = with_script(
+        "timers",
+        r#"
+        fn on_line(source, line) { set_timeout("later", 1); }
+        fn on_timer(name) { emit(`ws:timer,${name}`); }
+        "#,
+    );
+    routing.script_line("ws:x,1", Source::Serial);
+    std::thread::sleep(Duration::from_millis(5));
+    assert_eq!(routing.script_tick(), vec![("later".to_string(), vec!["ws:timer,later".to_string()])]);
+    // A timeout fires once
+    assert!(routing.script_tick().is_empty());
+}
+
+#[test]
+fn test_script_errors_keep_lines() {
+    let mut routing = with_script("errors", "fn on_line(source, line) { undefined_function() }");
+    routing.take_log();
+    assert_eq!(routing.script_line("ws:x,1", Source::Serial), (Some("ws:x,1".into()), vec![]));
+    assert!(routing.take_log()[0].starts_with("SCRIPT ERROR in on_line"));
+
+    let mut routing = with_script("syntax", "fn on_line(source, line) {");
+    assert!(routing.take_log()[0].starts_with("SCRIPT ERROR"));
+    assert_eq!(routing.script_line("ws:x,1", Source::Serial), (Some("ws:x,1".into()), vec![]));
+
+    // Runaway scripts are stopped
+    let mut routing = with_script("loop", "fn on_line(source, line) { loop {} }");
+    routing.take_log();
+    assert_eq!(routing.script_line("ws:x,1", Source::Serial).0, Some("ws:x,1".into()));
+    assert_eq!(routing.take_log().len(), 1);
+}
+
+// --- Profiles ---
+
+#[test]
+fn test_profile_switch() {
+    let mut config = Config::default();
+    config.mouse.enabled = false;
+    config.keys.insert("a".into(), "b".into());
+    config.profiles.insert(
+        "show".into(),
+        ProfileConfig {
+            mappings: Some(vec![MappingConfig {
+                id: Some("button1".into()),
+                emit: vec!["key:tap,a".into()],
+                ..Default::default()
+            }]),
+            keys: Some([("a".to_string(), "space".to_string())].into()),
+            mouse: Some(true),
+            ..Default::default()
+        },
+    );
+    let mut profiles = Profiles::new(&config);
+    assert_eq!(profiles.active(), "default");
+    assert_eq!(profiles.names(), vec!["default".to_string(), "show".to_string()]);
+
+    let show = profiles.switch("show").unwrap();
+    assert_eq!(profiles.active(), "show");
+    assert_eq!(show.mappings.len(), 1);
+    assert_eq!(show.keys["a"], "space");
+    assert!(show.enabled.mouse && show.enabled.keyboard);
+    // Unset overrides keep the base config
+    assert_eq!(show.osc_destinations[0].name, "default");
+
+    assert!(profiles.switch("missing").is_err());
+    assert_eq!(profiles.active(), "show");
+
+    let base = profiles.switch("default").unwrap();
+    assert!(base.mappings.is_empty());
+    assert_eq!(base.keys["a"], "b");
+    assert!(!base.enabled.mouse);
+}
+
+#[test]
+fn test_key_table() {
+    let mut config = Config::default();
+    config.keys.insert("a".into(), "space".into());
+    let routing = Routing::new(&config);
+    assert_eq!(routing.rename_key(Command::KeyDown("a".into())), Command::KeyDown("space".into()));
+    assert_eq!(routing.rename_key(Command::KeyTap("b".into())), Command::KeyTap("b".into()));
+    assert_eq!(routing.rename_key(Command::KeyType("a".into())), Command::KeyType("a".into()));
+}
@@ -0,0 +1,335 @@
+//! Tests for WebSocket broadcast messages.
+
+use mio_bridge::bridge::websocket::{infer_value, LastValueCache, WsMessage};
+use mio_bridge::bridge::ws_api::{self, Endpoint};
+use mio_bridge::bridge::ws_auth::{self, Access};
+use mio_bridge::bridge::ws_codec::WsEncoding;
+use mio_bridge::bridge::ws_static;
+use mio_bridge::config::{WebSocketConfig, WsClientConfig};
+use mio_bridge::bridge::ws_topics::TopicFilter;
+use mio_bridge::protocol::Source;
+use serde_json::{json, Value};
+use tokio_tungstenite::tungstenite::Message;
+
+// --- Value inference ---
+
+#[test]
+fn test_infer_int() {
+    assert_eq!(infer_value("512"), json!(512));
+}
+
+#[test]
+fn test_infer_float() {
+    assert_eq!(infer_value("0.25"), json!(0.25));
+}
+
+#[test]
+fn test_infer_bool_and_null() {
+    assert_eq!(infer_value("true"), json!(true));
+    assert_eq!(infer_value("false"), json!(false));
+    assert_eq!(infer_value("null"), Value::Null);
+}
+
+#[test]
+fn test_infer_string() {
+    assert_eq!(infer_value("hello"), json!("hello"));
+}
+
+#[test]
+fn test_infer_non_finite_is_string() {
+    assert_eq!(infer_value("NaN"), json!("NaN"));
+    assert_eq!(infer_value("inf"), json!("inf"));
+}
+
+#[test]
+fn test_infer_array() {
+    assert_eq!(infer_value("1,2,3"), json!([1, 2, 3]));
+    assert_eq!(infer_value("1, 0.5,on"), json!([1, 0.5, "on"]));
+}
+
+#[test]
+fn test_infer_embedded_json() {
+    assert_eq!(infer_value(r#"{"x":1,"y":2}"#), json!({"x": 1, "y": 2}));
+    assert_eq!(infer_value(r#""a, b""#), json!("a, b"));
+}
+
+#[test]
+fn test_infer_invalid_json_falls_back() {
+    assert_eq!(infer_value("[1,2"), json!(["[1", 2]));
+}
+
+// --- Broadcast messages ---
+
+#[test]
+fn test_broadcast_message_fields() {
+    let msg: Value = serde_json::from_str(&WsMessage::broadcast("pot", "512", Source::Serial).text).unwrap();
+    assert_eq!(msg["id"], json!("pot"));
+    assert_eq!(msg["value"], json!(512));
+    assert_eq!(msg["source"], json!("serial"));
+    assert!(msg["ts"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn test_broadcast_message_escapes_strings() {
+    let text = WsMessage::broadcast("say \"hi\"", "back\\slash", Source::Osc).text;
+    let msg: Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(msg["id"], json!("say \"hi\""));
+    assert_eq!(msg["value"], json!("back\\slash"));
+    assert_eq!(msg["source"], json!("osc"));
+}
+
+// --- Topic subscriptions ---
+
+#[test]
+fn test_topics_default_receives_everything() {
+    let filter = TopicFilter::from_request("/", None);
+    assert!(filter.matches("pot"));
+    assert!(filter.matches("raw"));
+}
+
+#[test]
+fn test_topics_from_query() {
+    let filter = TopicFilter::from_request("/", Some("topics=pot%2Ctemp*"));
+    assert!(filter.matches("pot"));
+    assert!(filter.matches("temp1"));
+    assert!(!filter.matches("light"));
+}
+
+#[test]
+fn test_topics_from_path() {
+    let filter = TopicFilter::from_request("/pot,light", None);
+    assert!(filter.matches("light"));
+    assert!(!filter.matches("temp"));
+}
+
+#[test]
+fn test_topics_control_messages() {
+    let mut filter = TopicFilter::default();
+    assert!(filter.apply_control(r#"{"subscribe":["pot","temp*"]}"#));
+    assert!(filter.matches("temp2"));
+    assert!(!filter.matches("light"));
+
+    assert!(filter.apply_control(r#"{"unsubscribe":"pot"}"#));
+    assert!(!filter.matches("pot"));
+    assert!(filter.matches("temp2"));
+}
+
+#[test]
+fn test_topics_ignores_other_messages() {
+    let mut filter = TopicFilter::default();
+    assert!(!filter.apply_control("key:tap,a"));
+    assert!(!filter.apply_control(r#"{"COMMAND":"LISTEN","DATA":"/a"}"#));
+    assert!(filter.matches("anything"));
+}
+
+// --- Last-value cache ---
+
+#[test]
+fn test_cache_keeps_latest_value_per_id() {
+    let cache = LastValueCache::default();
+    cache.insert(&WsMessage::broadcast("door", "open", Source::Serial));
+    cache.insert(&WsMessage::broadcast("door", "closed", Source::Serial));
+    cache.insert(&WsMessage::broadcast("temp", "21.5", Source::Serial));
+    cache.insert(&WsMessage::raw("not cached"));
+
+    let all = cache.snapshot(&TopicFilter::default());
+    assert_eq!(all.len(), 2);
+    let door: Value = serde_json::from_str(&all[0].text).unwrap();
+    assert_eq!(door["value"], json!("closed"));
+}
+
+#[test]
+fn test_cache_snapshot_respects_topics() {
+    let cache = LastValueCache::default();
+    cache.insert(&WsMessage::broadcast("door", "1", Source::Serial));
+    cache.insert(&WsMessage::broadcast("temp", "2", Source::Serial));
+
+    let snapshot = cache.snapshot(&TopicFilter::from_request("/", Some("topics=te*")));
+    assert_eq!(snapshot.len(), 1);
+    assert_eq!(snapshot[0].topic, "temp");
+}
+
+// --- Static files ---
+
+#[test]
+fn test_static_resolve() {
+    let root = std::env::temp_dir().join(format!("mio-static-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("css")).unwrap();
+    std::fs::write(root.join("index.html"), "<h1>hi</h1>").unwrap();
+    std::fs::write(root.join("css/app.css"), "body{}").unwrap();
+
+    assert_eq!(ws_static::resolve(&root, "/"), Some(root.join("index.html")));
+    assert_eq!(ws_static::resolve(&root, "/css/app.css"), Some(root.join("css/app.css")));
+    assert_eq!(ws_static::resolve(&root, "/missing.js"), None);
+    assert_eq!(ws_static::resolve(&root, "/../etc/passwd"), None);
+    assert_eq!(ws_static::resolve(&root, "/css/%2e%2e/index.html"), None);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_static_content_type() {
+    assert_eq!(ws_static::content_type(std::path::Path::new("a.JS")), "text/javascript; charset=utf-8");
+    assert_eq!(ws_static::content_type(std::path::Path::new("a.bin")), "application/octet-stream");
+}
+
+// --- Access control ---
+
+fn secured_config() -> WebSocketConfig {
+    WebSocketConfig {
+        token: Some("shared".into()),
+        allowed_commands: vec!["ws:".into()],
+        allowed_origins: vec!["http://localhost:8080".into()],
+        clients: vec![WsClientConfig {
+            name: "phone".into(),
+            token: "phone-secret".into(),
+            allowed_commands: vec!["key:tap".into()],
+        }],
+        ..WebSocketConfig::default()
+    }
+}
+
+#[test]
+fn test_access_open_by_default() {
+    let access = Access::new(&WebSocketConfig::default());
+    assert!(!access.requires_token());
+    let permissions = access.authenticate(None).unwrap();
+    assert!(permissions.allows("key:type,hello"));
+    // Browsers only from pages served on this port
+    assert!(access.origin_allowed(None, Some("localhost:8080")));
+    assert!(access.origin_allowed(Some("http://localhost:8080"), Some("localhost:8080")));
+    assert!(!access.origin_allowed(Some("http://evil.example"), Some("localhost:8080")));
+    assert!(!access.origin_allowed(Some("http://evil.example"), None));
+    assert_eq!(access.cors_origin(Some("http://evil.example")), None);
+}
+
+#[test]
+fn test_access_tokens() {
+    let access = Access::new(&secured_config());
+    assert!(access.requires_token());
+    assert!(access.authenticate(None).is_none());
+    assert!(access.authenticate(Some("wrong")).is_none());
+
+    let shared = access.authenticate(Some("shared")).unwrap();
+    assert!(shared.allows("ws:pot,1"));
+    assert!(!shared.allows("key:type,rm -rf"));
+
+    let phone = access.authenticate(Some("phone-secret")).unwrap();
+    assert_eq!(phone.name.as_deref(), Some("phone"));
+    assert!(phone.allows("key:tap,space"));
+    assert!(!phone.allows("ws:pot,1"));
+}
+
+#[test]
+fn test_access_origins() {
+    let access = Access::new(&secured_config());
+    assert!(access.origin_allowed(None, None));
+    assert!(access.origin_allowed(Some("http://localhost:8080"), Some("127.0.0.1:8080")));
+    assert!(access.origin_allowed(Some("https://mio.local"), Some("MIO.local")));
+    assert!(!access.origin_allowed(Some("http://evil.example"), Some("127.0.0.1:8080")));
+    assert_eq!(access.cors_origin(Some("http://localhost:8080")), Some("http://localhost:8080"));
+    assert_eq!(access.cors_origin(Some("http://evil.example")), None);
+    assert_eq!(access.cors_origin(None), None);
+
+    let any = Access::new(&WebSocketConfig {
+        allowed_origins: vec!["*".into()],
+        ..Default::default()
+    });
+    assert!(any.origin_allowed(Some("http://evil.example"), None));
+    assert_eq!(any.cors_origin(Some("http://evil.example")), Some("http://evil.example"));
+}
+
+#[test]
+fn test_request_token() {
+    assert_eq!(ws_auth::request_token(Some("topics=a&token=x%2By"), None), Some("x+y".into()));
+    assert_eq!(ws_auth::request_token(None, Some("Bearer abc")), Some("abc".into()));
+    assert_eq!(ws_auth::request_token(None, Some("bearer abc")), Some("abc".into()));
+    assert_eq!(ws_auth::request_token(None, Some("BEARER  abc ")), Some("abc".into()));
+    assert_eq!(ws_auth::request_token(None, Some("Basic abc")), None);
+    assert_eq!(ws_auth::request_token(None, Some("Bearerabc")), None);
+    assert_eq!(ws_auth::request_token(Some("topics=a"), None), None);
+    assert_eq!(ws_auth::auth_message(r#"{"auth":"abc"}"#), Some("abc".into()));
+    assert_eq!(ws_auth::strip_token(Some("VALUE&token=abc")), Some("VALUE".into()));
+    assert_eq!(ws_auth::strip_token(Some("token=abc")), None);
+    assert_eq!(ws_auth::strip_token(Some("HOST_INFO")), Some("HOST_INFO".into()));
+    assert_eq!(ws_auth::strip_token(None), None);
+}
+
+// --- Binary encodings ---
+
+#[test]
+fn test_encoding_negotiate() {
+    assert_eq!(WsEncoding::negotiate("mio.msgpack"), Some(WsEncoding::MsgPack));
+    assert_eq!(WsEncoding::negotiate("chat, mio.cbor, mio.json"), Some(WsEncoding::Cbor));
+    assert_eq!(WsEncoding::negotiate("chat"), None);
+}
+
+#[test]
+fn test_encoding_broadcast_frames() {
+    let msg = WsMessage::broadcast("pot", "512", Source::Serial);
+    assert!(matches!(msg.to_frame(WsEncoding::Json), Message::Text(_)));
+
+    let Message::Binary(bytes) = msg.to_frame(WsEncoding::MsgPack) else {
+        panic!("expected a binary frame");
+    };
+    let value: Value = rmp_serde::from_slice(&bytes).unwrap();
+    assert_eq!(value["id"], json!("pot"));
+    assert_eq!(value["value"], json!(512));
+
+    let Message::Binary(bytes) = msg.to_frame(WsEncoding::Cbor) else {
+        panic!("expected a binary frame");
+    };
+    let value: Value = ciborium::from_reader(bytes.as_ref()).unwrap();
+    assert_eq!(value["value"], json!(512));
+
+    // Raw payloads stay verbatim text
+    assert_eq!(WsMessage::raw("hello").to_frame(WsEncoding::Cbor), Message::text("hello"));
+}
+
+#[test]
+fn test_encoding_decode_inbound() {
+    let line = rmp_serde::to_vec(&"ws:pot,1").unwrap();
+    assert_eq!(WsEncoding::MsgPack.decode(&Message::binary(line)), Some("ws:pot,1".into()));
+
+    let mut control = Vec::new();
+    ciborium::into_writer(&json!({ "subscribe": ["pot"] }), &mut control).unwrap();
+    let text = WsEncoding::Cbor.decode(&Message::binary(control)).unwrap();
+    assert_eq!(serde_json::from_str::<Value>(&text).unwrap(), json!({ "subscribe": ["pot"] }));
+
+    assert_eq!(WsEncoding::Json.decode(&Message::binary(b"ws:a,1".to_vec())), Some("ws:a,1".into()));
+    assert_eq!(WsEncoding::MsgPack.decode(&Message::binary(vec![0xc1])), None);
+}
+
+// --- HTTP API ---
+
+#[test]
+fn test_api_endpoints() {
+    assert_eq!(Endpoint::from_path("/events"), Some(Endpoint::Events));
+    assert_eq!(Endpoint::from_path("/values/"), Some(Endpoint::Values));
+    assert_eq!(Endpoint::from_path("/command"), Some(Endpoint::Command));
+    assert_eq!(Endpoint::from_path("/dashboard"), None);
+    assert_eq!(Endpoint::Command.method(), "POST");
+}
+
+#[test]
+fn test_api_values_json() {
+    let cache = LastValueCache::default();
+    cache.insert(&WsMessage::broadcast("pot", "512", Source::Serial));
+    cache.insert(&WsMessage::broadcast("temp", "21.5", Source::Osc));
+
+    let values = ws_api::values_json(&cache, &TopicFilter::default());
+    assert_eq!(values["pot"]["value"], json!(512));
+    assert_eq!(values["temp"]["source"], json!("osc"));
+
+    let only_pot = ws_api::values_json(&cache, &TopicFilter::from_request("/", Some("topics=pot")));
+    assert_eq!(only_pot.as_object().unwrap().len(), 1);
+}
+
+#[test]
+fn test_api_sse_and_command_lines() {
+    assert_eq!(ws_api::sse_event(None, r#"{"id":"a"}"#), "data: {\"id\":\"a\"}\n\n");
+    assert_eq!(ws_api::sse_event(Some("lagged"), "1\n2"), "event: lagged\ndata: 1\ndata: 2\n\n");
+    assert_eq!(ws_api::command_lines("ws:a,1\r\n\n  key:tap,b \n"), vec!["ws:a,1", "key:tap,b"]);
+    assert_eq!(ws_api::allow_origin_header(Some("http://a")), "Access-Control-Allow-Origin: http://a\r\n");
+    assert_eq!(ws_api::allow_origin_header(None), "");
+}